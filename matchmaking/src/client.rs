@@ -0,0 +1,149 @@
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use crate::protocol::{ClientMessage, ErrorCode, ServerMessage, PROTOCOL_VERSION};
+
+#[derive(Debug)]
+pub enum ClientError {
+    Io(io::Error),
+    Server(ErrorCode),
+    /// The server sent something this client didn't expect at this point.
+    Unexpected(String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Io(err) => write!(f, "{}", err),
+            ClientError::Server(code) => write!(f, "server error: {}", code),
+            ClientError::Unexpected(line) => write!(f, "unexpected message: {}", line),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<io::Error> for ClientError {
+    fn from(err: io::Error) -> Self {
+        ClientError::Io(err)
+    }
+}
+
+/// Who a client got paired with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Peer {
+    pub code: String,
+    pub name: String,
+    pub addr: SocketAddr,
+}
+
+/// A blocking connection to the matchmaking server.
+pub struct Client {
+    writer: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl Client {
+    /// Connects and does the version handshake.
+    pub fn connect(addr: impl ToSocketAddrs, name: &str) -> Result<Client, ClientError> {
+        let writer = TcpStream::connect(addr)?;
+        let reader = BufReader::new(writer.try_clone()?);
+        let mut client = Client { writer, reader };
+
+        let name: String = name
+            .chars()
+            .map(|c| if c.is_whitespace() { '_' } else { c })
+            .collect();
+        client.send(&ClientMessage::Hello {
+            version: PROTOCOL_VERSION,
+            name,
+        })?;
+        match client.recv()? {
+            ServerMessage::Welcome { .. } => Ok(client),
+            other => Err(ClientError::Unexpected(other.to_string())),
+        }
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.writer.set_read_timeout(timeout)
+    }
+
+    pub fn send(&mut self, message: &ClientMessage) -> io::Result<()> {
+        writeln!(self.writer, "{}", message)
+    }
+
+    /// Reads the next message; server errors come back as `Err`.
+    pub fn recv(&mut self) -> Result<ServerMessage, ClientError> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(ClientError::Io(io::ErrorKind::UnexpectedEof.into()));
+        }
+        match line.trim_end().parse() {
+            Ok(ServerMessage::Error(code)) => Err(ClientError::Server(code)),
+            Ok(message) => Ok(message),
+            Err(_) => Err(ClientError::Unexpected(line)),
+        }
+    }
+
+    /// Opens a room and returns its code. Use [`Client::wait_for_peer`] to wait for a guest.
+    pub fn host(&mut self, port: u16) -> Result<String, ClientError> {
+        self.send(&ClientMessage::Host { port })?;
+        match self.recv()? {
+            ServerMessage::Room { code } => Ok(code),
+            other => Err(ClientError::Unexpected(other.to_string())),
+        }
+    }
+
+    pub fn join(&mut self, code: &str, port: u16) -> Result<Peer, ClientError> {
+        self.send(&ClientMessage::Join {
+            code: code.to_string(),
+            port,
+        })?;
+        self.wait_for_peer()
+    }
+
+    /// Queues for a quick match and blocks until someone else queues too.
+    pub fn queue(&mut self, port: u16) -> Result<Peer, ClientError> {
+        self.send(&ClientMessage::Queue { port })?;
+        self.wait_for_peer()
+    }
+
+    pub fn wait_for_peer(&mut self) -> Result<Peer, ClientError> {
+        loop {
+            match self.recv()? {
+                ServerMessage::Paired {
+                    code,
+                    peer_name,
+                    peer_addr,
+                } => {
+                    return Ok(Peer {
+                        code,
+                        name: peer_name,
+                        addr: peer_addr,
+                    })
+                }
+                ServerMessage::Queued => continue,
+                other => return Err(ClientError::Unexpected(other.to_string())),
+            }
+        }
+    }
+
+    pub fn relay(&mut self, payload: &[u8]) -> io::Result<()> {
+        self.send(&ClientMessage::Relay(payload.to_vec()))
+    }
+
+    pub fn recv_relayed(&mut self) -> Result<Vec<u8>, ClientError> {
+        match self.recv()? {
+            ServerMessage::Relayed(payload) => Ok(payload),
+            other => Err(ClientError::Unexpected(other.to_string())),
+        }
+    }
+
+    pub fn leave(&mut self) -> io::Result<()> {
+        self.send(&ClientMessage::Leave)
+    }
+}
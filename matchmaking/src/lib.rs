@@ -0,0 +1,11 @@
+//! Matchmaking and relay for online matches: clients say hello with their protocol
+//! version, then either host a room and share its code, join one by code, or queue
+//! for whoever comes next. Paired clients learn each other's address, and can fall
+//! back to relaying packets through the server.
+
+pub mod client;
+pub mod protocol;
+pub mod server;
+
+pub use client::{Client, ClientError, Peer};
+pub use server::Server;
@@ -0,0 +1,16 @@
+use matchmaking::Server;
+
+const DEFAULT_BIND: &str = "0.0.0.0:7878";
+
+fn main() -> std::io::Result<()> {
+    let bind = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| DEFAULT_BIND.to_string());
+    let server = Server::bind(&bind)?;
+    println!(
+        "Matchmaking server listening on {} (protocol v{})",
+        server.local_addr()?,
+        matchmaking::protocol::PROTOCOL_VERSION
+    );
+    server.run()
+}
@@ -0,0 +1,227 @@
+//! The line-based text protocol spoken over TCP between the game and the server.
+//!
+//! Every message is one line of space separated fields. Relayed packets are hex
+//! encoded so they can never contain a space or newline.
+
+use std::{fmt, net::SocketAddr, str::FromStr};
+
+/// Bumped whenever a message is added, removed or changes shape.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+pub const ROOM_CODE_LEN: usize = 4;
+
+/// Letters used for room codes, without the ones that are easy to misread.
+pub const ROOM_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientMessage {
+    /// Must be the first message on a connection.
+    Hello {
+        version: u32,
+        name: String,
+    },
+    /// Opens a room; the port is where this client listens for game packets.
+    Host {
+        port: u16,
+    },
+    Join {
+        code: String,
+        port: u16,
+    },
+    /// Pairs with the next client that also queues.
+    Queue {
+        port: u16,
+    },
+    /// Forwards a packet to the paired peer through the server.
+    Relay(Vec<u8>),
+    Leave,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerMessage {
+    Welcome {
+        version: u32,
+    },
+    Room {
+        code: String,
+    },
+    Queued,
+    /// The peer's address as seen by the server, with the port it asked for.
+    Paired {
+        code: String,
+        peer_name: String,
+        peer_addr: SocketAddr,
+    },
+    Relayed(Vec<u8>),
+    PeerLeft,
+    Error(ErrorCode),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    VersionMismatch { server_version: u32 },
+    NotWelcomed,
+    NoSuchRoom,
+    RoomFull,
+    AlreadyInRoom,
+    NotPaired,
+    Malformed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError(pub String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "malformed message: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 == 1 {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn field<'a>(
+    fields: &mut impl Iterator<Item = &'a str>,
+    line: &str,
+) -> Result<&'a str, ParseError> {
+    fields.next().ok_or_else(|| ParseError(line.to_string()))
+}
+
+fn parsed<'a, T: FromStr>(
+    fields: &mut impl Iterator<Item = &'a str>,
+    line: &str,
+) -> Result<T, ParseError> {
+    field(fields, line)?
+        .parse()
+        .map_err(|_| ParseError(line.to_string()))
+}
+
+fn hex<'a>(fields: &mut impl Iterator<Item = &'a str>, line: &str) -> Result<Vec<u8>, ParseError> {
+    decode_hex(field(fields, line)?).ok_or_else(|| ParseError(line.to_string()))
+}
+
+impl fmt::Display for ClientMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientMessage::Hello { version, name } => write!(f, "HELLO {} {}", version, name),
+            ClientMessage::Host { port } => write!(f, "HOST {}", port),
+            ClientMessage::Join { code, port } => write!(f, "JOIN {} {}", code, port),
+            ClientMessage::Queue { port } => write!(f, "QUEUE {}", port),
+            ClientMessage::Relay(payload) => write!(f, "RELAY {}", encode_hex(payload)),
+            ClientMessage::Leave => write!(f, "LEAVE"),
+        }
+    }
+}
+
+impl FromStr for ClientMessage {
+    type Err = ParseError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut fields = line.split_whitespace();
+        let message = match field(&mut fields, line)? {
+            "HELLO" => ClientMessage::Hello {
+                version: parsed(&mut fields, line)?,
+                name: field(&mut fields, line)?.to_string(),
+            },
+            "HOST" => ClientMessage::Host {
+                port: parsed(&mut fields, line)?,
+            },
+            "JOIN" => ClientMessage::Join {
+                code: field(&mut fields, line)?.to_ascii_uppercase(),
+                port: parsed(&mut fields, line)?,
+            },
+            "QUEUE" => ClientMessage::Queue {
+                port: parsed(&mut fields, line)?,
+            },
+            "RELAY" => ClientMessage::Relay(hex(&mut fields, line)?),
+            "LEAVE" => ClientMessage::Leave,
+            _ => return Err(ParseError(line.to_string())),
+        };
+        Ok(message)
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorCode::VersionMismatch { server_version } => {
+                write!(f, "version-mismatch {}", server_version)
+            }
+            ErrorCode::NotWelcomed => write!(f, "not-welcomed"),
+            ErrorCode::NoSuchRoom => write!(f, "no-such-room"),
+            ErrorCode::RoomFull => write!(f, "room-full"),
+            ErrorCode::AlreadyInRoom => write!(f, "already-in-room"),
+            ErrorCode::NotPaired => write!(f, "not-paired"),
+            ErrorCode::Malformed => write!(f, "malformed"),
+        }
+    }
+}
+
+impl fmt::Display for ServerMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerMessage::Welcome { version } => write!(f, "WELCOME {}", version),
+            ServerMessage::Room { code } => write!(f, "ROOM {}", code),
+            ServerMessage::Queued => write!(f, "QUEUED"),
+            ServerMessage::Paired {
+                code,
+                peer_name,
+                peer_addr,
+            } => write!(f, "PAIRED {} {} {}", code, peer_name, peer_addr),
+            ServerMessage::Relayed(payload) => write!(f, "RELAYED {}", encode_hex(payload)),
+            ServerMessage::PeerLeft => write!(f, "PEER_LEFT"),
+            ServerMessage::Error(code) => write!(f, "ERROR {}", code),
+        }
+    }
+}
+
+impl FromStr for ServerMessage {
+    type Err = ParseError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut fields = line.split_whitespace();
+        let message = match field(&mut fields, line)? {
+            "WELCOME" => ServerMessage::Welcome {
+                version: parsed(&mut fields, line)?,
+            },
+            "ROOM" => ServerMessage::Room {
+                code: field(&mut fields, line)?.to_string(),
+            },
+            "QUEUED" => ServerMessage::Queued,
+            "PAIRED" => ServerMessage::Paired {
+                code: field(&mut fields, line)?.to_string(),
+                peer_name: field(&mut fields, line)?.to_string(),
+                peer_addr: parsed(&mut fields, line)?,
+            },
+            "RELAYED" => ServerMessage::Relayed(hex(&mut fields, line)?),
+            "PEER_LEFT" => ServerMessage::PeerLeft,
+            "ERROR" => ServerMessage::Error(match field(&mut fields, line)? {
+                "version-mismatch" => ErrorCode::VersionMismatch {
+                    server_version: parsed(&mut fields, line)?,
+                },
+                "not-welcomed" => ErrorCode::NotWelcomed,
+                "no-such-room" => ErrorCode::NoSuchRoom,
+                "room-full" => ErrorCode::RoomFull,
+                "already-in-room" => ErrorCode::AlreadyInRoom,
+                "not-paired" => ErrorCode::NotPaired,
+                "malformed" => ErrorCode::Malformed,
+                _ => return Err(ParseError(line.to_string())),
+            }),
+            _ => return Err(ParseError(line.to_string())),
+        };
+        Ok(message)
+    }
+}
@@ -0,0 +1,357 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Write},
+    net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::protocol::{
+    ClientMessage, ErrorCode, ServerMessage, PROTOCOL_VERSION, ROOM_CODE_ALPHABET, ROOM_CODE_LEN,
+};
+
+type ClientId = u64;
+
+/// Messages waiting to be written to one client. A client that lets this many pile up
+/// is too slow to keep and gets disconnected.
+const OUTGOING_QUEUE_LEN: usize = 256;
+
+/// How long one write to a client may block its writer thread.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Connection {
+    /// Drained by the client's writer thread, so nothing writes to a socket while the
+    /// state is locked.
+    outgoing: SyncSender<ServerMessage>,
+    /// Only used to shut the socket down when the client falls behind.
+    stream: TcpStream,
+    ip: IpAddr,
+    name: Option<String>,
+    port: u16,
+    room: Option<String>,
+    peer: Option<ClientId>,
+}
+
+struct Room {
+    host: ClientId,
+    guest: Option<ClientId>,
+}
+
+struct State {
+    next_id: ClientId,
+    clients: HashMap<ClientId, Connection>,
+    rooms: HashMap<String, Room>,
+    queue: Option<ClientId>,
+    rng: u64,
+}
+
+/// Replies produced by one incoming message, and whether the sender should be dropped.
+#[derive(Default)]
+struct Outcome {
+    messages: Vec<(ClientId, ServerMessage)>,
+    close: bool,
+}
+
+impl Outcome {
+    fn reply(id: ClientId, message: ServerMessage) -> Self {
+        Outcome {
+            messages: vec![(id, message)],
+            close: false,
+        }
+    }
+}
+
+impl State {
+    fn new() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or_default();
+        State {
+            next_id: 0,
+            clients: HashMap::new(),
+            rooms: HashMap::new(),
+            queue: None,
+            rng: seed | 1,
+        }
+    }
+
+    fn room_code(&mut self) -> String {
+        loop {
+            let code: String = (0..ROOM_CODE_LEN)
+                .map(|_| {
+                    // xorshift64
+                    self.rng ^= self.rng << 13;
+                    self.rng ^= self.rng >> 7;
+                    self.rng ^= self.rng << 17;
+                    ROOM_CODE_ALPHABET[(self.rng % ROOM_CODE_ALPHABET.len() as u64) as usize]
+                        as char
+                })
+                .collect();
+            if !self.rooms.contains_key(&code) {
+                return code;
+            }
+        }
+    }
+
+    fn connect(
+        &mut self,
+        outgoing: SyncSender<ServerMessage>,
+        stream: TcpStream,
+        ip: IpAddr,
+    ) -> ClientId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.clients.insert(
+            id,
+            Connection {
+                outgoing,
+                stream,
+                ip,
+                name: None,
+                port: 0,
+                room: None,
+                peer: None,
+            },
+        );
+        id
+    }
+
+    fn busy(&self, id: ClientId) -> bool {
+        self.queue == Some(id) || self.clients[&id].room.is_some()
+    }
+
+    fn pair(&mut self, code: &str, a: ClientId, b: ClientId) -> Outcome {
+        let paired_with = |state: &State, peer: ClientId| {
+            let peer = &state.clients[&peer];
+            ServerMessage::Paired {
+                code: code.to_string(),
+                peer_name: peer.name.clone().unwrap_or_default(),
+                peer_addr: SocketAddr::new(peer.ip, peer.port),
+            }
+        };
+        let messages = vec![(a, paired_with(self, b)), (b, paired_with(self, a))];
+        for (id, peer) in [(a, b), (b, a)] {
+            let client = self.clients.get_mut(&id).unwrap();
+            client.room = Some(code.to_string());
+            client.peer = Some(peer);
+        }
+        Outcome {
+            messages,
+            close: false,
+        }
+    }
+
+    fn leave(&mut self, id: ClientId) -> Outcome {
+        let mut outcome = Outcome::default();
+        if self.queue == Some(id) {
+            self.queue = None;
+        }
+        let Some(client) = self.clients.get_mut(&id) else {
+            return outcome;
+        };
+        let room = client.room.take();
+        let peer = client.peer.take();
+        if let Some(room) = room {
+            self.rooms.remove(&room);
+        }
+        if let Some(peer) = peer.and_then(|peer| self.clients.get_mut(&peer).map(|c| (peer, c))) {
+            let (peer_id, peer) = peer;
+            peer.room = None;
+            peer.peer = None;
+            outcome.messages.push((peer_id, ServerMessage::PeerLeft));
+        }
+        outcome
+    }
+
+    fn handle(&mut self, id: ClientId, message: Option<ClientMessage>) -> Outcome {
+        let Some(message) = message else {
+            return Outcome::reply(id, ServerMessage::Error(ErrorCode::Malformed));
+        };
+        let welcomed = self.clients[&id].name.is_some();
+
+        match message {
+            ClientMessage::Hello { version, name } => {
+                if version != PROTOCOL_VERSION {
+                    let mut outcome = Outcome::reply(
+                        id,
+                        ServerMessage::Error(ErrorCode::VersionMismatch {
+                            server_version: PROTOCOL_VERSION,
+                        }),
+                    );
+                    outcome.close = true;
+                    return outcome;
+                }
+                self.clients.get_mut(&id).unwrap().name = Some(name);
+                Outcome::reply(
+                    id,
+                    ServerMessage::Welcome {
+                        version: PROTOCOL_VERSION,
+                    },
+                )
+            }
+            _ if !welcomed => Outcome::reply(id, ServerMessage::Error(ErrorCode::NotWelcomed)),
+            ClientMessage::Host { .. }
+            | ClientMessage::Join { .. }
+            | ClientMessage::Queue { .. }
+                if self.busy(id) =>
+            {
+                Outcome::reply(id, ServerMessage::Error(ErrorCode::AlreadyInRoom))
+            }
+            ClientMessage::Host { port } => {
+                let code = self.room_code();
+                self.rooms.insert(
+                    code.clone(),
+                    Room {
+                        host: id,
+                        guest: None,
+                    },
+                );
+                let client = self.clients.get_mut(&id).unwrap();
+                client.port = port;
+                client.room = Some(code.clone());
+                Outcome::reply(id, ServerMessage::Room { code })
+            }
+            ClientMessage::Join { code, port } => {
+                let Some(room) = self.rooms.get_mut(&code) else {
+                    return Outcome::reply(id, ServerMessage::Error(ErrorCode::NoSuchRoom));
+                };
+                if room.guest.is_some() {
+                    return Outcome::reply(id, ServerMessage::Error(ErrorCode::RoomFull));
+                }
+                room.guest = Some(id);
+                let host = room.host;
+                self.clients.get_mut(&id).unwrap().port = port;
+                self.pair(&code, host, id)
+            }
+            ClientMessage::Queue { port } => {
+                self.clients.get_mut(&id).unwrap().port = port;
+                match self.queue.take() {
+                    Some(waiting) => {
+                        let code = self.room_code();
+                        self.rooms.insert(
+                            code.clone(),
+                            Room {
+                                host: waiting,
+                                guest: Some(id),
+                            },
+                        );
+                        self.pair(&code, waiting, id)
+                    }
+                    None => {
+                        self.queue = Some(id);
+                        Outcome::reply(id, ServerMessage::Queued)
+                    }
+                }
+            }
+            ClientMessage::Relay(payload) => match self.clients[&id].peer {
+                Some(peer) => Outcome::reply(peer, ServerMessage::Relayed(payload)),
+                None => Outcome::reply(id, ServerMessage::Error(ErrorCode::NotPaired)),
+            },
+            ClientMessage::Leave => self.leave(id),
+        }
+    }
+
+    /// Queues the messages; the writer threads do the writing.
+    fn send(&self, outcome: Outcome) {
+        for (id, message) in outcome.messages {
+            let Some(client) = self.clients.get(&id) else {
+                continue;
+            };
+            match client.outgoing.try_send(message) {
+                // Its reader thread sees the shutdown and cleans up.
+                Err(TrySendError::Full(_)) => {
+                    let _ = client.stream.shutdown(Shutdown::Both);
+                }
+                // The writer already failed, which also shuts the socket down.
+                Err(TrySendError::Disconnected(_)) | Ok(()) => {}
+            }
+        }
+    }
+}
+
+/// Pairs clients by room code or a quick-match queue, and relays packets between
+/// pairs that can't reach each other directly.
+pub struct Server {
+    listener: TcpListener,
+    state: Arc<Mutex<State>>,
+}
+
+impl Server {
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Server> {
+        Ok(Server {
+            listener: TcpListener::bind(addr)?,
+            state: Arc::new(Mutex::new(State::new())),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts connections until the listener fails, one thread per client.
+    pub fn run(self) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            let state = self.state.clone();
+            thread::spawn(move || {
+                if let Err(err) = handle_connection(stream, state) {
+                    eprintln!("Connection error: {}", err);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    pub fn spawn(self) -> JoinHandle<io::Result<()>> {
+        thread::spawn(move || self.run())
+    }
+}
+
+fn handle_connection(stream: TcpStream, state: Arc<Mutex<State>>) -> io::Result<()> {
+    let ip = stream.peer_addr()?.ip();
+    let reader = BufReader::new(stream.try_clone()?);
+    let writer = stream.try_clone()?;
+    writer.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let (outgoing, queued) = mpsc::sync_channel(OUTGOING_QUEUE_LEN);
+    let writer = thread::spawn(move || write_messages(writer, queued));
+    let id = state.lock().unwrap().connect(outgoing, stream, ip);
+
+    for line in reader.lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let mut state = state.lock().unwrap();
+        let outcome = state.handle(id, line.parse().ok());
+        let close = outcome.close;
+        state.send(outcome);
+        if close {
+            break;
+        }
+    }
+
+    {
+        let mut state = state.lock().unwrap();
+        let outcome = state.leave(id);
+        state.send(outcome);
+        // Dropping the sender lets the writer finish what's queued and stop.
+        state.clients.remove(&id);
+    }
+    let _ = writer.join();
+    Ok(())
+}
+
+/// Writes a client's messages in order until its connection is removed. A failed or
+/// timed out write shuts the socket down, so its reader thread stops too.
+fn write_messages(mut stream: TcpStream, queued: Receiver<ServerMessage>) {
+    for message in queued.iter() {
+        if writeln!(stream, "{}", message).is_err() {
+            let _ = stream.shutdown(Shutdown::Both);
+            return;
+        }
+    }
+}
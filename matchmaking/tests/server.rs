@@ -0,0 +1,154 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpStream},
+    thread,
+    time::Duration,
+};
+
+use matchmaking::{
+    protocol::{ErrorCode, PROTOCOL_VERSION},
+    Client, ClientError, Server,
+};
+
+fn start_server() -> SocketAddr {
+    let server = Server::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    server.spawn();
+    addr
+}
+
+fn connect(addr: SocketAddr, name: &str) -> Client {
+    let client = Client::connect(addr, name).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    client
+}
+
+#[test]
+fn host_and_join_by_room_code() {
+    let addr = start_server();
+    let mut host = connect(addr, "host");
+    let mut guest = connect(addr, "guest");
+
+    let code = host.host(7000).unwrap();
+    let host_peer = thread::spawn(move || (host.wait_for_peer().unwrap(), host));
+    let guest_peer = guest.join(&code.to_lowercase(), 7001).unwrap();
+    let (host_peer, mut host) = host_peer.join().unwrap();
+
+    assert_eq!(guest_peer.code, code);
+    assert_eq!(guest_peer.name, "host");
+    assert_eq!(guest_peer.addr, "127.0.0.1:7000".parse().unwrap());
+    assert_eq!(host_peer.name, "guest");
+    assert_eq!(host_peer.addr, "127.0.0.1:7001".parse().unwrap());
+
+    host.relay(&[1, 2, 3, 255]).unwrap();
+    assert_eq!(guest.recv_relayed().unwrap(), vec![1, 2, 3, 255]);
+    guest.relay(b"ack").unwrap();
+    assert_eq!(host.recv_relayed().unwrap(), b"ack".to_vec());
+}
+
+#[test]
+fn quick_match_queue_pairs_clients() {
+    let addr = start_server();
+    let mut first = connect(addr, "first");
+    let mut second = connect(addr, "second");
+
+    let first_peer = thread::spawn(move || first.queue(7100).unwrap());
+    // Give the first client time to reach the front of the queue.
+    thread::sleep(Duration::from_millis(100));
+    let second_peer = second.queue(7101).unwrap();
+    let first_peer = first_peer.join().unwrap();
+
+    assert_eq!(first_peer.name, "second");
+    assert_eq!(second_peer.name, "first");
+    assert_eq!(first_peer.code, second_peer.code);
+}
+
+#[test]
+fn peer_leaving_is_reported() {
+    let addr = start_server();
+    let mut host = connect(addr, "host");
+    let mut guest = connect(addr, "guest");
+
+    let code = host.host(7200).unwrap();
+    guest.join(&code, 7201).unwrap();
+    host.wait_for_peer().unwrap();
+
+    drop(guest);
+    assert_eq!(
+        host.recv().unwrap(),
+        matchmaking::protocol::ServerMessage::PeerLeft
+    );
+}
+
+#[test]
+fn joining_a_missing_or_full_room_fails() {
+    let addr = start_server();
+    let mut host = connect(addr, "host");
+    let mut guest = connect(addr, "guest");
+    let mut late = connect(addr, "late");
+
+    assert!(matches!(
+        guest.join("ZZZZ", 7300),
+        Err(ClientError::Server(ErrorCode::NoSuchRoom))
+    ));
+
+    let code = host.host(7301).unwrap();
+    guest.join(&code, 7302).unwrap();
+    assert!(matches!(
+        late.join(&code, 7303),
+        Err(ClientError::Server(ErrorCode::RoomFull))
+    ));
+}
+
+#[test]
+fn mismatched_protocol_version_is_rejected() {
+    let addr = start_server();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    writeln!(stream, "HELLO {} old-client", PROTOCOL_VERSION + 1).unwrap();
+
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(
+        line.trim_end(),
+        format!("ERROR version-mismatch {}", PROTOCOL_VERSION)
+    );
+
+    line.clear();
+    assert_eq!(
+        reader.read_line(&mut line).unwrap(),
+        0,
+        "server should hang up"
+    );
+}
+
+#[test]
+fn a_client_that_stops_reading_does_not_hold_up_the_others() {
+    let addr = start_server();
+    let mut host = connect(addr, "host");
+    let mut guest = connect(addr, "guest");
+    let mut late = connect(addr, "late");
+
+    let code = host.host(7400).unwrap();
+    guest.join(&code, 7401).unwrap();
+    host.wait_for_peer().unwrap();
+
+    // Far more than the guest's socket buffers and outgoing queue hold together.
+    let payload = vec![0xab; 16 * 1024];
+    for _ in 0..1000 {
+        host.relay(&payload).unwrap();
+    }
+    late.host(7402).unwrap();
+
+    // The guest fell behind and was dropped.
+    assert_eq!(
+        host.recv().unwrap(),
+        matchmaking::protocol::ServerMessage::PeerLeft
+    );
+    drop(guest);
+}
@@ -0,0 +1,148 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// How a value changes with the number of clashes so far: `base + per_clash * clashes`,
+/// kept within `min..=max`.
+#[derive(Debug, Clone, Copy, Reflect)]
+pub struct ClashCurve {
+    pub base: f32,
+    pub per_clash: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl ClashCurve {
+    pub const fn constant(value: f32) -> Self {
+        ClashCurve {
+            base: value,
+            per_clash: 0.0,
+            min: value,
+            max: value,
+        }
+    }
+
+    pub fn at(&self, clashes: u32) -> f32 {
+        (self.base + self.per_clash * clashes as f32).clamp(self.min, self.max)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Reflect)]
+pub enum ClashReset {
+    /// The counter only goes back to zero when the points are reset.
+    #[default]
+    PerMatch,
+    /// The counter goes back to zero every time the players are reset.
+    PerRound,
+}
+
+#[derive(Debug, Resource, Clone, Reflect)]
+#[reflect(Resource)]
+pub struct ClashRules {
+    pub reset: ClashReset,
+    /// Extra distance moved per frame while walking.
+    pub speed_bonus: ClashCurve,
+    /// Length of the defender's parry window, in seconds.
+    pub parry_window: ClashCurve,
+    pub pushback_distance: ClashCurve,
+    /// How long the pushback tween takes, in seconds.
+    pub pushback_duration: ClashCurve,
+}
+
+impl Default for ClashRules {
+    fn default() -> Self {
+        ClashPreset::default().rules()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Reflect, Serialize, Deserialize)]
+pub enum ClashPreset {
+    /// Every clash adds a point of walk speed for the rest of the match.
+    #[default]
+    Classic,
+    /// Clashes do nothing beyond the pushback.
+    Flat,
+    /// Each round builds up faster movement, a tighter parry window and bigger pushback.
+    Escalating,
+    /// Clashes widen the parry window, so long exchanges favour the defender.
+    Standoff,
+}
+
+impl ClashPreset {
+    pub fn next(self) -> Self {
+        match self {
+            ClashPreset::Classic => ClashPreset::Flat,
+            ClashPreset::Flat => ClashPreset::Escalating,
+            ClashPreset::Escalating => ClashPreset::Standoff,
+            ClashPreset::Standoff => ClashPreset::Classic,
+        }
+    }
+
+    pub fn rules(self) -> ClashRules {
+        let parry_window = 0.016 * 4.0;
+        match self {
+            ClashPreset::Classic => ClashRules {
+                reset: ClashReset::PerMatch,
+                speed_bonus: ClashCurve {
+                    base: 0.0,
+                    per_clash: 1.0,
+                    min: 0.0,
+                    max: f32::INFINITY,
+                },
+                parry_window: ClashCurve::constant(parry_window),
+                pushback_distance: ClashCurve::constant(300.0),
+                pushback_duration: ClashCurve::constant(0.2),
+            },
+            ClashPreset::Flat => ClashRules {
+                reset: ClashReset::PerRound,
+                speed_bonus: ClashCurve::constant(0.0),
+                parry_window: ClashCurve::constant(parry_window),
+                pushback_distance: ClashCurve::constant(300.0),
+                pushback_duration: ClashCurve::constant(0.2),
+            },
+            ClashPreset::Escalating => ClashRules {
+                reset: ClashReset::PerRound,
+                speed_bonus: ClashCurve {
+                    base: 0.0,
+                    per_clash: 2.0,
+                    min: 0.0,
+                    max: 10.0,
+                },
+                parry_window: ClashCurve {
+                    base: parry_window,
+                    per_clash: -0.008,
+                    min: 0.032,
+                    max: parry_window,
+                },
+                pushback_distance: ClashCurve {
+                    base: 300.0,
+                    per_clash: 50.0,
+                    min: 300.0,
+                    max: 600.0,
+                },
+                pushback_duration: ClashCurve {
+                    base: 0.2,
+                    per_clash: 0.05,
+                    min: 0.2,
+                    max: 0.5,
+                },
+            },
+            ClashPreset::Standoff => ClashRules {
+                reset: ClashReset::PerMatch,
+                speed_bonus: ClashCurve::constant(0.0),
+                parry_window: ClashCurve {
+                    base: parry_window,
+                    per_clash: 0.008,
+                    min: parry_window,
+                    max: parry_window * 2.0,
+                },
+                pushback_distance: ClashCurve {
+                    base: 300.0,
+                    per_clash: -25.0,
+                    min: 150.0,
+                    max: 300.0,
+                },
+                pushback_duration: ClashCurve::constant(0.2),
+            },
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::player::{frames_to_secs, Player};
+
+/// A hit that isn't parried deals damage and leaves the fighter in hitstun. Any hit
+/// that lands before the hitstun wears off is part of the same combo: it deals less
+/// damage the longer the combo runs and gives less hitstun, so every combo drops in
+/// the end.
+pub struct ComboPlugin;
+
+impl Plugin for ComboPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ComboHit>()
+            .add_event::<ComboEnded>()
+            .register_type::<ComboRules>()
+            .insert_resource(ComboRules::default())
+            .add_systems(Update, log_combos);
+    }
+}
+
+/// Sent for every hit after the first in a combo.
+#[derive(Event, Debug)]
+pub struct ComboHit {
+    pub attacker: Entity,
+    pub defender: Entity,
+    /// Hits so far, this one included.
+    pub hits: u32,
+    pub damage: f32,
+}
+
+/// Sent when the defender gets out of hitstun, or is knocked out in it.
+#[derive(Event, Debug)]
+pub struct ComboEnded {
+    /// Whoever landed the last hit.
+    pub attacker: Option<Entity>,
+    pub defender: Entity,
+    pub hits: u32,
+    pub damage: f32,
+    pub knocked_out: bool,
+}
+
+#[derive(Debug, Resource, Clone, Reflect)]
+#[reflect(Resource)]
+pub struct ComboRules {
+    pub max_health: f32,
+    /// Damage of the first hit; later hits are scaled down from it.
+    pub hit_damage: f32,
+    /// How much less each hit after the first deals, as a fraction of `hit_damage`.
+    pub scaling_per_hit: f32,
+    /// Hits never deal less than this fraction of `hit_damage`.
+    pub min_scaling: f32,
+    pub hitstun_frames: u32,
+    /// How much shorter the hitstun gets with each hit in the combo.
+    pub hitstun_decay_frames: u32,
+    pub min_hitstun_frames: u32,
+}
+
+impl Default for ComboRules {
+    fn default() -> Self {
+        ComboRules {
+            max_health: 100.0,
+            hit_damage: 40.0,
+            scaling_per_hit: 0.15,
+            min_scaling: 0.4,
+            // Long enough for an all-rounder's cooldown to come back for a follow-up;
+            // slower fighters get more through `CombatStats::hitstun_scale`.
+            hitstun_frames: 70,
+            hitstun_decay_frames: 4,
+            min_hitstun_frames: 20,
+        }
+    }
+}
+
+impl ComboRules {
+    /// Damage of the `hit`th hit of a combo, counting from 1.
+    pub fn damage(&self, hit: u32) -> f32 {
+        let scaling = 1.0 - self.scaling_per_hit * hit.saturating_sub(1) as f32;
+        self.hit_damage * scaling.max(self.min_scaling)
+    }
+
+    /// Hitstun left by the `hit`th hit of a combo, counting from 1.
+    pub fn hitstun(&self, hit: u32) -> Duration {
+        let frames = self
+            .hitstun_frames
+            .saturating_sub(self.hitstun_decay_frames * hit.saturating_sub(1))
+            .max(self.min_hitstun_frames);
+        Duration::from_secs_f32(frames_to_secs(frames))
+    }
+}
+
+fn log_combos(
+    mut ev_combo_hit: EventReader<ComboHit>,
+    mut ev_combo_ended: EventReader<ComboEnded>,
+    players: Query<&Player>,
+) {
+    let number = |entity: Entity| players.get(entity).map_or(0, |player| player.player_number);
+    for hit in ev_combo_hit.read() {
+        println!(
+            "Player {:?} combo hit {} on player {:?} for {:.0}",
+            number(hit.attacker),
+            hit.hits,
+            number(hit.defender),
+            hit.damage
+        );
+    }
+    for ended in ev_combo_ended.read() {
+        if ended.hits > 1 {
+            println!(
+                "Player {:?} took a {} hit combo for {:.0}",
+                number(ended.defender),
+                ended.hits,
+                ended.damage
+            );
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::special::{SpecialMove, SpecialMoveRules};
+
+/// What a player picks in the lobby, before any colors or controls are attached. All
+/// of a fighter's balance data lives here; anything not in it is the same for everyone.
+#[derive(Debug)]
+pub struct FighterDefinition {
+    pub name: &'static str,
+    /// One line for the lobby.
+    pub description: &'static str,
+    pub size: f32,
+    pub movement: MovementStats,
+    pub combat: CombatStats,
+    pub moves: Moveset,
+}
+
+#[derive(Debug)]
+pub struct CombatStats {
+    /// Reach of a plain attack.
+    pub attack_range: f32,
+    /// Time between attacks, which throws share.
+    pub attack_cooldown_frames: u32,
+    /// Multiplies the damage of every hit and throw the fighter lands.
+    pub damage_scale: f32,
+    /// Multiplies how far a clash pushes the other fighter away.
+    pub pushback_scale: f32,
+    /// Multiplies the hitstun of every hit and throw the fighter lands, so a slow
+    /// attack still comes back in time for a follow-up.
+    pub hitstun_scale: f32,
+}
+
+impl CombatStats {
+    /// For hits from a fighter who has left the match.
+    pub const UNSCALED: CombatStats = CombatStats {
+        attack_range: 0.0,
+        attack_cooldown_frames: 0,
+        damage_scale: 1.0,
+        pushback_scale: 1.0,
+        hitstun_scale: 1.0,
+    };
+}
+
+/// The special moves a fighter has. Everyone has a reversal; a command for a move the
+/// fighter doesn't have does nothing.
+#[derive(Debug)]
+pub struct Moveset {
+    /// Invulnerable from start to finish.
+    pub reversal: SpecialMoveRules,
+    pub long_strike: Option<SpecialMoveRules>,
+    /// Range doesn't apply; see [`crate::projectile::ProjectileRules`].
+    pub projectile: Option<SpecialMoveRules>,
+}
+
+impl Moveset {
+    pub fn special(&self, special: SpecialMove) -> Option<&SpecialMoveRules> {
+        match special {
+            SpecialMove::Reversal => Some(&self.reversal),
+            SpecialMove::LongStrike => self.long_strike.as_ref(),
+            SpecialMove::Projectile => self.projectile.as_ref(),
+        }
+    }
+}
+
+/// How a fighter gets around. Speeds are in units per second, and accelerations in
+/// units per second per second.
+#[derive(Debug)]
+pub struct MovementStats {
+    pub walk_speed: f32,
+    /// Holding forward out of a dash keeps the fighter running at this speed.
+    pub run_speed: f32,
+    /// How fast the fighter speeds up towards the direction held.
+    pub acceleration: f32,
+    /// How fast the fighter slows down with nothing held, or turns around.
+    pub deceleration: f32,
+    pub dash_speed: f32,
+    pub dash_frames: u32,
+    pub backdash_speed: f32,
+    pub backdash_frames: u32,
+    /// Frames at the start of a backdash where nothing can hit or grab the fighter.
+    pub backdash_invulnerable_frames: u32,
+}
+
+pub const FIGHTERS: &[FighterDefinition] = &[
+    FighterDefinition {
+        name: "Square",
+        description: "All-rounder",
+        size: 100.0,
+        movement: MovementStats {
+            walk_speed: 650.0,
+            run_speed: 900.0,
+            acceleration: 6000.0,
+            deceleration: 8000.0,
+            dash_speed: 1400.0,
+            dash_frames: 12,
+            backdash_speed: 1100.0,
+            backdash_frames: 16,
+            backdash_invulnerable_frames: 8,
+        },
+        combat: CombatStats {
+            attack_range: 100.0,
+            attack_cooldown_frames: 60,
+            damage_scale: 1.0,
+            pushback_scale: 1.0,
+            hitstun_scale: 1.0,
+        },
+        moves: Moveset {
+            reversal: SpecialMoveRules {
+                cost: 50.0,
+                range: 130.0,
+                frames: 30,
+                unparryable: true,
+            },
+            long_strike: Some(SpecialMoveRules {
+                cost: 30.0,
+                range: 350.0,
+                frames: 45,
+                unparryable: false,
+            }),
+            projectile: Some(SpecialMoveRules {
+                cost: 25.0,
+                range: 0.0,
+                frames: 30,
+                unparryable: false,
+            }),
+        },
+    },
+    FighterDefinition {
+        name: "Dart",
+        description: "Fast, short reach",
+        size: 80.0,
+        movement: MovementStats {
+            walk_speed: 780.0,
+            run_speed: 1100.0,
+            acceleration: 9000.0,
+            deceleration: 10000.0,
+            dash_speed: 1700.0,
+            dash_frames: 10,
+            backdash_speed: 1300.0,
+            backdash_frames: 14,
+            backdash_invulnerable_frames: 8,
+        },
+        combat: CombatStats {
+            attack_range: 80.0,
+            attack_cooldown_frames: 45,
+            damage_scale: 0.8,
+            pushback_scale: 0.8,
+            hitstun_scale: 1.0,
+        },
+        moves: Moveset {
+            reversal: SpecialMoveRules {
+                cost: 50.0,
+                range: 110.0,
+                frames: 24,
+                unparryable: true,
+            },
+            long_strike: None,
+            projectile: Some(SpecialMoveRules {
+                cost: 20.0,
+                range: 0.0,
+                frames: 24,
+                unparryable: false,
+            }),
+        },
+    },
+    FighterDefinition {
+        name: "Slab",
+        description: "Slow, long reach",
+        size: 130.0,
+        movement: MovementStats {
+            walk_speed: 480.0,
+            run_speed: 650.0,
+            acceleration: 3500.0,
+            deceleration: 6000.0,
+            dash_speed: 1100.0,
+            dash_frames: 14,
+            backdash_speed: 800.0,
+            backdash_frames: 18,
+            backdash_invulnerable_frames: 6,
+        },
+        combat: CombatStats {
+            attack_range: 150.0,
+            attack_cooldown_frames: 80,
+            damage_scale: 1.25,
+            pushback_scale: 1.5,
+            hitstun_scale: 1.25,
+        },
+        moves: Moveset {
+            reversal: SpecialMoveRules {
+                cost: 60.0,
+                range: 160.0,
+                frames: 36,
+                unparryable: true,
+            },
+            long_strike: Some(SpecialMoveRules {
+                cost: 30.0,
+                range: 420.0,
+                frames: 50,
+                unparryable: false,
+            }),
+            projectile: None,
+        },
+    },
+];
+
+pub const PALETTES: [Color; 4] = [
+    Color::rgb(1.0, 0.7, 0.6),
+    Color::rgb(0.8, 1.0, 0.6),
+    Color::rgb(0.6, 0.8, 1.0),
+    Color::rgb(1.0, 0.9, 0.5),
+];
+
+/// Colors picked from the Okabe-Ito set so neighbouring slots differ in lightness as
+/// well as hue for each kind of color blindness.
+const PROTANOPIA_PALETTES: [Color; 4] = [
+    Color::rgb(0.0, 0.45, 0.7),
+    Color::rgb(0.94, 0.89, 0.26),
+    Color::rgb(0.8, 0.47, 0.65),
+    Color::rgb(0.75, 0.75, 0.75),
+];
+
+const DEUTERANOPIA_PALETTES: [Color; 4] = [
+    Color::rgb(0.9, 0.62, 0.0),
+    Color::rgb(0.0, 0.45, 0.7),
+    Color::rgb(0.75, 0.75, 0.75),
+    Color::rgb(0.34, 0.71, 0.91),
+];
+
+const TRITANOPIA_PALETTES: [Color; 4] = [
+    Color::rgb(0.84, 0.37, 0.0),
+    Color::rgb(0.0, 0.62, 0.45),
+    Color::rgb(0.75, 0.75, 0.75),
+    Color::rgb(0.8, 0.47, 0.65),
+];
+
+/// Which colors the lobby offers. A fighter keeps its slot in the set, so switching
+/// sets mid-match recolors everyone.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Reflect, Serialize, Deserialize)]
+pub enum PaletteSet {
+    #[default]
+    Standard,
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+impl PaletteSet {
+    pub fn colors(self) -> &'static [Color; 4] {
+        match self {
+            PaletteSet::Standard => &PALETTES,
+            PaletteSet::Protanopia => &PROTANOPIA_PALETTES,
+            PaletteSet::Deuteranopia => &DEUTERANOPIA_PALETTES,
+            PaletteSet::Tritanopia => &TRITANOPIA_PALETTES,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            PaletteSet::Standard => PaletteSet::Protanopia,
+            PaletteSet::Protanopia => PaletteSet::Deuteranopia,
+            PaletteSet::Deuteranopia => PaletteSet::Tritanopia,
+            PaletteSet::Tritanopia => PaletteSet::Standard,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{combo::ComboRules, player::frames_to_secs};
+
+    #[test]
+    fn every_fighter_is_back_in_time_to_follow_up_a_hit() {
+        let combo_rules = ComboRules::default();
+        for fighter in FIGHTERS {
+            let hitstun = combo_rules.hitstun(1).mul_f32(fighter.combat.hitstun_scale);
+            let cooldown = frames_to_secs(fighter.combat.attack_cooldown_frames);
+            assert!(
+                hitstun.as_secs_f32() > cooldown,
+                "{} can't follow up its own hit",
+                fighter.name
+            );
+        }
+    }
+}
@@ -0,0 +1,608 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::{BoxedFuture, HashSet},
+};
+use serde::Deserialize;
+
+use crate::{
+    combo::{ComboEnded, ComboHit, ComboRules},
+    player::{ClashCounter, Player},
+    round::RoundClock,
+    special::MeterRules,
+    theme::UiTheme,
+    MatchMode, MatchSettings, Points,
+};
+
+/// The in-match HUD, built from `assets/hud.hud.ron`. The layout picks the widgets and
+/// where they go; each widget type is bound to the game state it shows. Native builds
+/// rebuild the HUD whenever the file is saved.
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<HudLayout>()
+            .register_asset_loader(HudLayoutLoader)
+            .add_systems(Startup, load_hud_layout)
+            .add_systems(
+                Update,
+                (
+                    rebuild_hud,
+                    sync_player_widgets,
+                    update_hud_texts,
+                    update_bars,
+                    update_combo_texts,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Used until the asset has loaded, and for good on the web build if `assets/` wasn't deployed.
+const BUILT_IN_LAYOUT: &str = include_str!("../assets/hud.hud.ron");
+const LAYOUT_PATH: &str = "hud.hud.ron";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Side {
+    /// Odd player numbers.
+    Left,
+    /// Even player numbers.
+    Right,
+}
+
+impl Side {
+    fn of(player_number: u8) -> Side {
+        if player_number % 2 == 1 {
+            Side::Left
+        } else {
+            Side::Right
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub enum Widget {
+    /// One score line per fighter on that side, in the fighter's color.
+    Scores(Side),
+    /// One health bar per fighter on that side.
+    HealthBars(Side),
+    /// One meter bar per fighter on that side.
+    MeterBars(Side),
+    RoundClock,
+    /// Clashes so far, the clash rules and any drawn rounds.
+    ClashCounter,
+    /// The combo a fighter on that side is landing, left up for a moment after it ends.
+    Combo(Side),
+    Label(String),
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct HudStyle {
+    pub width: Option<Val>,
+    pub height: Option<Val>,
+    pub justify_content: Option<JustifyContent>,
+    pub align_items: Option<AlignItems>,
+    pub gap: Option<Val>,
+    pub padding: Option<Val>,
+}
+
+impl HudStyle {
+    fn to_style(&self, flex_direction: FlexDirection) -> Style {
+        Style {
+            flex_direction,
+            width: self.width.unwrap_or_default(),
+            height: self.height.unwrap_or_default(),
+            justify_content: self.justify_content.unwrap_or_default(),
+            align_items: self.align_items.unwrap_or_default(),
+            row_gap: self.gap.unwrap_or_default(),
+            column_gap: self.gap.unwrap_or_default(),
+            padding: UiRect::all(self.padding.unwrap_or_default()),
+            ..default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum HudNode {
+    Row {
+        #[serde(default)]
+        style: HudStyle,
+        children: Vec<HudNode>,
+    },
+    Column {
+        #[serde(default)]
+        style: HudStyle,
+        children: Vec<HudNode>,
+    },
+    Widget {
+        widget: Widget,
+        #[serde(default)]
+        style: HudStyle,
+        /// Overrides the theme.
+        #[serde(default)]
+        font_size: Option<f32>,
+        #[serde(default)]
+        color: Option<[f32; 4]>,
+        #[serde(default)]
+        justify: JustifyText,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HudTheme {
+    pub font_size: f32,
+    pub color: [f32; 4],
+    /// Behind health and meter bars; health is filled in the fighter's color.
+    pub health_background: [f32; 4],
+    pub meter_color: [f32; 4],
+}
+
+#[derive(Debug, Clone, Asset, TypePath, Deserialize)]
+pub struct HudLayout {
+    pub theme: HudTheme,
+    pub root: HudNode,
+}
+
+fn parse_layout(bytes: &[u8]) -> Result<HudLayout, ron::error::SpannedError> {
+    ron::Options::default()
+        .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+        .from_bytes(bytes)
+}
+
+#[derive(Default)]
+struct HudLayoutLoader;
+
+impl AssetLoader for HudLayoutLoader {
+    type Asset = HudLayout;
+    type Settings = ();
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<HudLayout, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            Ok(parse_layout(&bytes)?)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["hud.ron"]
+    }
+}
+
+#[derive(Resource)]
+struct HudLayoutHandle(Handle<HudLayout>);
+
+#[derive(Component)]
+struct HudRoot;
+
+/// A text widget and what it shows.
+#[derive(Debug, Component)]
+enum HudText {
+    PlayerScore(Entity),
+    RoundClock,
+    ClashCounter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bar {
+    Health,
+    Meter,
+}
+
+/// A column that gets one child per fighter on its side.
+#[derive(Debug, Component)]
+struct PlayerColumn {
+    side: Side,
+    /// Score lines if `None`.
+    bar: Option<Bar>,
+    style: TextStyle,
+    justify: JustifyText,
+    bar_size: (Val, Val),
+    bar_background: Color,
+    meter_color: Color,
+}
+
+/// Which fighter a score line or health bar in a [`PlayerColumn`] belongs to.
+#[derive(Debug, Component)]
+struct PlayerWidget(Entity);
+
+#[derive(Debug, Component)]
+struct BarFill {
+    player: Entity,
+    bar: Bar,
+    meter_color: Color,
+}
+
+#[derive(Debug, Component)]
+struct ComboText {
+    side: Side,
+    /// Elapsed seconds at which the finished combo is cleared.
+    clear_at: Option<f32>,
+}
+
+/// How long a finished combo stays on screen.
+const COMBO_LINGER_SECS: f32 = 1.5;
+
+fn load_hud_layout(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let layout =
+        parse_layout(BUILT_IN_LAYOUT.as_bytes()).expect("the built-in HUD layout should parse");
+    spawn_hud(&mut commands, &layout);
+    commands.insert_resource(HudLayoutHandle(asset_server.load(LAYOUT_PATH)));
+}
+
+fn rebuild_hud(
+    mut commands: Commands,
+    mut ev_layout: EventReader<AssetEvent<HudLayout>>,
+    layouts: Res<Assets<HudLayout>>,
+    handle: Res<HudLayoutHandle>,
+    roots: Query<Entity, With<HudRoot>>,
+) {
+    for event in ev_layout.read() {
+        let (AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id }) = event
+        else {
+            continue;
+        };
+        if *id != handle.0.id() {
+            continue;
+        }
+        let Some(layout) = layouts.get(*id) else {
+            continue;
+        };
+        for root in roots.iter() {
+            commands.entity(root).despawn_recursive();
+        }
+        spawn_hud(&mut commands, layout);
+        println!("Loaded HUD layout from {}", LAYOUT_PATH);
+    }
+}
+
+fn spawn_hud(commands: &mut Commands, layout: &HudLayout) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(HudRoot)
+        .with_children(|parent| spawn_node(parent, &layout.root, &layout.theme));
+}
+
+fn text_bundle(value: &str, style: TextStyle, justify: JustifyText) -> TextBundle {
+    TextBundle {
+        text: Text {
+            sections: vec![TextSection {
+                value: value.to_string(),
+                style,
+            }],
+            justify,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+fn spawn_node(parent: &mut ChildBuilder, node: &HudNode, theme: &HudTheme) {
+    let (style, children, flex_direction) = match node {
+        HudNode::Row { style, children } => (style, children, FlexDirection::Row),
+        HudNode::Column { style, children } => (style, children, FlexDirection::Column),
+        HudNode::Widget {
+            widget,
+            style,
+            font_size,
+            color,
+            justify,
+        } => {
+            let text_style = TextStyle {
+                font_size: font_size.unwrap_or(theme.font_size),
+                color: Color::rgba_from_array(color.unwrap_or(theme.color)),
+                font: Default::default(),
+            };
+            spawn_widget(parent, widget, style, text_style, *justify, theme);
+            return;
+        }
+    };
+    parent
+        .spawn(NodeBundle {
+            style: style.to_style(flex_direction),
+            ..default()
+        })
+        .with_children(|parent| {
+            for child in children {
+                spawn_node(parent, child, theme);
+            }
+        });
+}
+
+fn spawn_widget(
+    parent: &mut ChildBuilder,
+    widget: &Widget,
+    style: &HudStyle,
+    text_style: TextStyle,
+    justify: JustifyText,
+    theme: &HudTheme,
+) {
+    match widget {
+        Widget::Scores(side) | Widget::HealthBars(side) | Widget::MeterBars(side) => {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: match side {
+                            Side::Left => AlignItems::Start,
+                            Side::Right => AlignItems::End,
+                        },
+                        row_gap: style.gap.unwrap_or_default(),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .insert(PlayerColumn {
+                    side: *side,
+                    bar: match widget {
+                        Widget::HealthBars(_) => Some(Bar::Health),
+                        Widget::MeterBars(_) => Some(Bar::Meter),
+                        _ => None,
+                    },
+                    style: text_style,
+                    justify,
+                    bar_size: (
+                        style.width.unwrap_or(Val::Px(300.0)),
+                        style.height.unwrap_or(Val::Px(20.0)),
+                    ),
+                    bar_background: Color::rgba_from_array(theme.health_background),
+                    meter_color: Color::rgba_from_array(theme.meter_color),
+                });
+        }
+        Widget::RoundClock => {
+            parent
+                .spawn(text_bundle("--", text_style, justify))
+                .insert(HudText::RoundClock);
+        }
+        Widget::ClashCounter => {
+            parent
+                .spawn(text_bundle("", text_style, justify))
+                .insert(HudText::ClashCounter);
+        }
+        Widget::Combo(side) => {
+            parent
+                .spawn(text_bundle("", text_style, justify))
+                .insert(ComboText {
+                    side: *side,
+                    clear_at: None,
+                });
+        }
+        Widget::Label(value) => {
+            parent.spawn(text_bundle(value, text_style, justify));
+        }
+    }
+}
+
+/// Adds a score line or health bar for every fighter that doesn't have one yet, and
+/// removes those of fighters that are gone.
+fn sync_player_widgets(
+    mut commands: Commands,
+    players: Query<(Entity, &Player)>,
+    widgets: Query<(Entity, &PlayerWidget)>,
+    columns: Query<(Entity, &PlayerColumn, Option<&Children>)>,
+) {
+    for (widget_entity, widget) in widgets.iter() {
+        if !players.contains(widget.0) {
+            commands.entity(widget_entity).despawn_recursive();
+        }
+    }
+
+    for (column_entity, column, children) in columns.iter() {
+        let shown: HashSet<Entity> = widgets
+            .iter_many(children.into_iter().flatten())
+            .map(|(_, widget)| widget.0)
+            .collect();
+        let mut missing: Vec<_> = players
+            .iter()
+            .filter(|(entity, player)| {
+                Side::of(player.player_number) == column.side && !shown.contains(entity)
+            })
+            .collect();
+        missing.sort_by_key(|(_, player)| player.player_number);
+
+        commands.entity(column_entity).with_children(|parent| {
+            for (entity, player) in missing {
+                if let Some(bar) = column.bar {
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                width: column.bar_size.0,
+                                height: column.bar_size.1,
+                                justify_content: match column.side {
+                                    Side::Left => JustifyContent::Start,
+                                    Side::Right => JustifyContent::End,
+                                },
+                                ..default()
+                            },
+                            background_color: column.bar_background.into(),
+                            ..default()
+                        })
+                        .insert(PlayerWidget(entity))
+                        .with_children(|parent| {
+                            parent
+                                .spawn(NodeBundle {
+                                    style: Style {
+                                        width: Val::Percent(100.0),
+                                        height: Val::Percent(100.0),
+                                        ..default()
+                                    },
+                                    ..default()
+                                })
+                                .insert(BarFill {
+                                    player: entity,
+                                    bar,
+                                    meter_color: column.meter_color,
+                                });
+                        });
+                } else {
+                    parent
+                        .spawn(text_bundle(
+                            &format!("Player {}: 0", player.player_number),
+                            column.style.clone(),
+                            column.justify,
+                        ))
+                        .insert(PlayerWidget(entity))
+                        .insert(HudText::PlayerScore(entity));
+                }
+            }
+        });
+    }
+}
+
+fn update_hud_texts(
+    mut texts: Query<(&HudText, &mut Text)>,
+    points: Res<Points>,
+    clash_counter: Res<ClashCounter>,
+    round_clock: Res<RoundClock>,
+    match_settings: Res<MatchSettings>,
+    theme: Res<UiTheme>,
+    players: Query<(Entity, &Player)>,
+) {
+    for (hud_text, mut text) in texts.iter_mut() {
+        let value = match hud_text {
+            HudText::PlayerScore(entity) => {
+                let Ok((_, player)) = players.get(*entity) else {
+                    continue;
+                };
+                let color = theme.player_color(player);
+                if text.sections[0].style.color != color {
+                    text.sections[0].style.color = color;
+                }
+                let score = points.scores.get(entity).copied().unwrap_or_default();
+                match match_settings.mode {
+                    MatchMode::FreeForAll => format!("Player {}: {}", player.player_number, score),
+                    MatchMode::Teams => {
+                        let team_score: u32 = players
+                            .iter()
+                            .filter(|(_, teammate)| teammate.team == player.team)
+                            .filter_map(|(teammate, _)| points.scores.get(&teammate))
+                            .sum();
+                        format!(
+                            "Player {}: {} (Team {}: {})",
+                            player.player_number, score, player.team, team_score
+                        )
+                    }
+                }
+            }
+            HudText::ClashCounter => {
+                let mut value = format!(
+                    "Clash Counter: {} ({:?})",
+                    clash_counter.0, match_settings.clash_preset
+                );
+                if points.draws > 0 {
+                    value += &format!("\nDraws: {}", points.draws);
+                }
+                value
+            }
+            HudText::RoundClock => match &round_clock.0 {
+                Some(timer) => format!("{}", timer.remaining_secs().ceil() as u32),
+                None => "--".to_string(),
+            },
+        };
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
+
+fn update_bars(
+    mut fills: Query<(&BarFill, &mut Style, &mut BackgroundColor)>,
+    players: Query<&Player>,
+    theme: Res<UiTheme>,
+    combo_rules: Res<ComboRules>,
+    meter_rules: Res<MeterRules>,
+) {
+    for (fill, mut style, mut background) in fills.iter_mut() {
+        let Ok(player) = players.get(fill.player) else {
+            continue;
+        };
+        let (color, fraction) = match fill.bar {
+            Bar::Health => (
+                theme.player_color(player),
+                player.health / combo_rules.max_health,
+            ),
+            Bar::Meter => (fill.meter_color, player.meter / meter_rules.max_meter),
+        };
+        if background.0 != color {
+            background.0 = color;
+        }
+        let percent = (fraction * 100.0).clamp(0.0, 100.0);
+        if style.width != Val::Percent(percent) {
+            style.width = Val::Percent(percent);
+        }
+    }
+}
+
+fn combo_line(hits: u32, damage: f32) -> String {
+    format!("{} hits\n{:.0} damage", hits, damage)
+}
+
+/// Shows combos of two hits or more on the attacker's side.
+fn update_combo_texts(
+    time: Res<Time>,
+    mut ev_combo_hit: EventReader<ComboHit>,
+    mut ev_combo_ended: EventReader<ComboEnded>,
+    players: Query<&Player>,
+    mut texts: Query<(&mut ComboText, &mut Text)>,
+) {
+    let side_of = |entity: Entity| {
+        players
+            .get(entity)
+            .ok()
+            .map(|player| Side::of(player.player_number))
+    };
+    let mut updates: Vec<(Side, String, Option<f32>)> = Vec::new();
+    for hit in ev_combo_hit.read() {
+        let Ok(defender) = players.get(hit.defender) else {
+            continue;
+        };
+        if let Some(side) = side_of(hit.attacker) {
+            updates.push((side, combo_line(hit.hits, defender.combo_damage), None));
+        }
+    }
+    for ended in ev_combo_ended.read() {
+        if ended.hits < 2 {
+            continue;
+        }
+        if let Some(side) = ended.attacker.and_then(side_of) {
+            let mut line = combo_line(ended.hits, ended.damage);
+            if ended.knocked_out {
+                line += "\nK.O.!";
+            }
+            updates.push((side, line, Some(time.elapsed_seconds() + COMBO_LINGER_SECS)));
+        }
+    }
+
+    for (mut combo, mut text) in texts.iter_mut() {
+        for (side, line, clear_at) in updates.iter() {
+            if *side == combo.side {
+                text.sections[0].value.clone_from(line);
+                combo.clear_at = *clear_at;
+            }
+        }
+        if combo
+            .clear_at
+            .is_some_and(|clear_at| time.elapsed_seconds() >= clear_at)
+        {
+            text.sections[0].value.clear();
+            combo.clear_at = None;
+        }
+    }
+}
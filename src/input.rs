@@ -0,0 +1,209 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{pause::not_paused, player::Player, ControlDevice, ControlPerPlayer, Controls};
+
+pub struct PlayerInputPlugin;
+
+impl Plugin for PlayerInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<PlayerInput>()
+            .register_type::<InputBufferSettings>()
+            .insert_resource(InputBufferSettings::default())
+            .add_systems(Update, sample_player_input.in_set(InputSet::Sample))
+            .configure_sets(
+                Update,
+                (InputSet::Sample, InputSet::Override)
+                    .chain()
+                    .run_if(not_paused),
+            );
+    }
+}
+
+/// Input is sampled once per frame in `Sample`, and anything that wants to drive a
+/// fighter without the keyboard (training dummy, replays) writes in `Override`.
+/// Neither runs while the match is paused.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum InputSet {
+    Sample,
+    Override,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Reflect, Serialize, Deserialize)]
+pub struct InputSample {
+    pub left: bool,
+    pub right: bool,
+    pub attack: bool,
+    /// Spends meter on a special move; see [`crate::special`].
+    #[serde(default)]
+    pub special: bool,
+    /// Grabs a fighter close by, or techs a grab; see [`crate::throw`].
+    #[serde(default)]
+    pub throw: bool,
+}
+
+/// The input a fighter acts on this frame, along with the previous frame's sample so
+/// presses can be detected without going back to the keyboard.
+#[derive(Debug, Default, Component, Reflect)]
+pub struct PlayerInput {
+    pub current: InputSample,
+    pub previous: InputSample,
+}
+
+impl PlayerInput {
+    pub fn push(&mut self, sample: InputSample) {
+        self.previous = self.current;
+        self.current = sample;
+    }
+
+    /// Replaces this frame's sample without losing last frame's.
+    pub fn set_current(&mut self, sample: InputSample) {
+        self.current = sample;
+    }
+
+    pub fn attack_just_pressed(&self) -> bool {
+        self.current.attack && !self.previous.attack
+    }
+
+    pub fn special_just_pressed(&self) -> bool {
+        self.current.special && !self.previous.special
+    }
+
+    pub fn throw_just_pressed(&self) -> bool {
+        self.current.throw && !self.previous.throw
+    }
+
+    pub fn left_just_pressed(&self) -> bool {
+        self.current.left && !self.previous.left
+    }
+
+    pub fn right_just_pressed(&self) -> bool {
+        self.current.right && !self.previous.right
+    }
+}
+
+/// How many frames a press is held before it is dropped. Attacks are buffered
+/// through `Wiff`, `Clashing` and the attack cooldown; a press that ends up used
+/// as a parry is held to `parry_frames` instead.
+#[derive(Debug, Resource, Reflect)]
+#[reflect(Resource)]
+pub struct InputBufferSettings {
+    pub attack_frames: u32,
+    pub parry_frames: u32,
+    /// Two presses of the same direction this close together are a dash.
+    pub double_tap_frames: u32,
+}
+
+impl Default for InputBufferSettings {
+    fn default() -> Self {
+        InputBufferSettings {
+            attack_frames: 6,
+            parry_frames: 0,
+            double_tap_frames: 12,
+        }
+    }
+}
+
+/// Presses waiting for the first frame they can be acted on, stored as seconds since the press.
+#[derive(Debug, Default, Component)]
+pub struct InputBuffer {
+    pub attack: Option<f32>,
+    /// The last press of each direction, kept to spot a double tap.
+    pub left_tap: Option<f32>,
+    pub right_tap: Option<f32>,
+}
+
+/// The keyboard can be shared by up to four players, each on their own keys.
+pub const KEYBOARD_LAYOUTS: [ControlPerPlayer; 4] = [
+    ControlPerPlayer {
+        left: KeyCode::KeyA,
+        right: KeyCode::KeyD,
+        attack: KeyCode::KeyS,
+        special: KeyCode::KeyW,
+        throw: KeyCode::KeyQ,
+    },
+    ControlPerPlayer {
+        left: KeyCode::ArrowLeft,
+        right: KeyCode::ArrowRight,
+        attack: KeyCode::ArrowDown,
+        special: KeyCode::ArrowUp,
+        throw: KeyCode::ShiftRight,
+    },
+    ControlPerPlayer {
+        left: KeyCode::KeyJ,
+        right: KeyCode::KeyL,
+        attack: KeyCode::KeyK,
+        // I resets the round.
+        special: KeyCode::KeyO,
+        throw: KeyCode::KeyP,
+    },
+    ControlPerPlayer {
+        left: KeyCode::Numpad4,
+        right: KeyCode::Numpad6,
+        attack: KeyCode::Numpad5,
+        special: KeyCode::Numpad8,
+        throw: KeyCode::Numpad9,
+    },
+];
+
+/// Everything needed to read an [`InputSample`] off any [`ControlDevice`].
+#[derive(SystemParam)]
+pub struct DeviceInput<'w> {
+    keyboard_input: Res<'w, ButtonInput<KeyCode>>,
+    gamepad_buttons: Res<'w, ButtonInput<GamepadButton>>,
+    gamepad_axes: Res<'w, Axis<GamepadAxis>>,
+    pub gamepads: Res<'w, Gamepads>,
+}
+
+impl DeviceInput<'_> {
+    pub fn sample(&self, device: &ControlDevice) -> InputSample {
+        match device {
+            ControlDevice::Keyboard(keys) => InputSample {
+                left: self.keyboard_input.pressed(keys.left),
+                right: self.keyboard_input.pressed(keys.right),
+                attack: self.keyboard_input.pressed(keys.attack),
+                special: self.keyboard_input.pressed(keys.special),
+                throw: self.keyboard_input.pressed(keys.throw),
+            },
+            ControlDevice::Gamepad(gamepad) => {
+                let button = |button_type| {
+                    self.gamepad_buttons
+                        .pressed(GamepadButton::new(*gamepad, button_type))
+                };
+                let stick_x = self
+                    .gamepad_axes
+                    .get(GamepadAxis::new(*gamepad, GamepadAxisType::LeftStickX))
+                    .unwrap_or_default();
+                InputSample {
+                    left: button(GamepadButtonType::DPadLeft) || stick_x < -0.5,
+                    right: button(GamepadButtonType::DPadRight) || stick_x > 0.5,
+                    attack: button(GamepadButtonType::South),
+                    special: button(GamepadButtonType::West),
+                    throw: button(GamepadButtonType::North),
+                }
+            }
+        }
+    }
+
+    pub fn start_pressed(&self) -> bool {
+        self.keyboard_input.just_pressed(KeyCode::Enter)
+            || self.gamepads.iter().any(|gamepad| {
+                self.gamepad_buttons
+                    .just_pressed(GamepadButton::new(gamepad, GamepadButtonType::Start))
+            })
+    }
+}
+
+fn sample_player_input(
+    device_input: DeviceInput,
+    controls: Res<Controls>,
+    mut query: Query<(Entity, &mut PlayerInput), With<Player>>,
+) {
+    for (entity, mut input) in query.iter_mut() {
+        let sample = match controls.control_map.get(&entity) {
+            Some(device) => device_input.sample(device),
+            None => InputSample::default(),
+        };
+        input.push(sample);
+    }
+}
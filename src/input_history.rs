@@ -0,0 +1,179 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::{
+    input::{InputSample, InputSet, PlayerInput},
+    player::{secs_to_frames, Player, PlayerState},
+};
+
+pub struct InputHistoryPlugin;
+
+impl Plugin for InputHistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(InputHistorySettings::default())
+            .add_systems(Startup, spawn_input_history_root)
+            .add_systems(
+                Update,
+                (toggle_input_history, spawn_input_history_text).before(InputSet::Sample),
+            )
+            .add_systems(
+                PostUpdate,
+                (record_input_history, update_input_history_text).chain(),
+            );
+    }
+}
+
+#[derive(Debug, Resource)]
+pub struct InputHistorySettings {
+    pub enabled: bool,
+    pub max_entries: usize,
+}
+
+impl Default for InputHistorySettings {
+    fn default() -> Self {
+        InputHistorySettings {
+            enabled: false,
+            max_entries: 12,
+        }
+    }
+}
+
+/// One run of identical input; a new entry starts whenever the sample or the
+/// fighter's state changes, so hits and parries line up with the inputs around them.
+#[derive(Debug, Clone, Copy)]
+struct HistoryEntry {
+    sample: InputSample,
+    state: PlayerState,
+    secs: f32,
+}
+
+#[derive(Debug, Default, Component)]
+pub struct InputHistory {
+    entries: VecDeque<HistoryEntry>,
+}
+
+#[derive(Component)]
+struct InputHistoryRoot;
+
+#[derive(Component)]
+struct InputHistoryText(Entity);
+
+fn toggle_input_history(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut settings: ResMut<InputHistorySettings>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F4) {
+        settings.enabled = !settings.enabled;
+    }
+}
+
+fn spawn_input_history_root(mut commands: Commands) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(96.0),
+                bottom: Val::Percent(12.0),
+                left: Val::Percent(2.0),
+                justify_content: JustifyContent::SpaceBetween,
+                ..default()
+            },
+            visibility: Visibility::Hidden,
+            ..default()
+        })
+        .insert(InputHistoryRoot);
+}
+
+fn spawn_input_history_text(
+    mut commands: Commands,
+    new_players: Query<Entity, Added<Player>>,
+    root: Query<Entity, With<InputHistoryRoot>>,
+) {
+    let Ok(root) = root.get_single() else {
+        return;
+    };
+    for entity in new_players.iter() {
+        commands.entity(entity).insert(InputHistory::default());
+        commands.entity(root).with_children(|parent| {
+            parent
+                .spawn(TextBundle {
+                    text: Text {
+                        sections: vec![TextSection {
+                            value: String::new(),
+                            style: TextStyle {
+                                font_size: 24.0,
+                                color: Color::WHITE,
+                                font: Default::default(),
+                            },
+                        }],
+                        justify: JustifyText::Left,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(InputHistoryText(entity));
+        });
+    }
+}
+
+fn record_input_history(
+    time: Res<Time>,
+    settings: Res<InputHistorySettings>,
+    mut query: Query<(&Player, &PlayerInput, &mut InputHistory)>,
+) {
+    for (player, input, mut history) in query.iter_mut() {
+        match history.entries.front_mut() {
+            Some(entry) if entry.sample == input.current && entry.state == player.state => {
+                entry.secs += time.delta_seconds();
+            }
+            _ => {
+                history.entries.push_front(HistoryEntry {
+                    sample: input.current,
+                    state: player.state,
+                    secs: time.delta_seconds(),
+                });
+                history.entries.truncate(settings.max_entries);
+            }
+        }
+    }
+}
+
+fn update_input_history_text(
+    mut commands: Commands,
+    settings: Res<InputHistorySettings>,
+    players: Query<(&Player, &InputHistory)>,
+    mut root: Query<&mut Visibility, With<InputHistoryRoot>>,
+    mut texts: Query<(Entity, &InputHistoryText, &mut Text)>,
+) {
+    if let Ok(mut visibility) = root.get_single_mut() {
+        *visibility = if settings.enabled {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+    }
+    for (text_entity, history_text, mut text) in texts.iter_mut() {
+        let Ok((player, history)) = players.get(history_text.0) else {
+            commands.entity(text_entity).despawn_recursive();
+            continue;
+        };
+        if !settings.enabled {
+            continue;
+        }
+        let mut value = format!("P{} inputs\n", player.player_number);
+        for entry in history.entries.iter() {
+            value.push_str(&format!(
+                "{:>4}f  {} {} {} {} {}  {:?}\n",
+                secs_to_frames(entry.secs),
+                if entry.sample.left { "<" } else { " " },
+                if entry.sample.right { ">" } else { " " },
+                if entry.sample.attack { "A" } else { " " },
+                if entry.sample.special { "S" } else { " " },
+                if entry.sample.throw { "T" } else { " " },
+                entry.state,
+            ));
+        }
+        text.sections[0].value = value;
+    }
+}
@@ -0,0 +1,408 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{
+    fighter::{FighterDefinition, FIGHTERS, PALETTES},
+    input::{DeviceInput, InputSample},
+    pause::on_off,
+    player::{Lineup, LineupEntry},
+    profile::not_editing,
+    save::SaveData,
+    ControlDevice, GameState, MatchSettings,
+};
+
+pub struct LobbyPlugin;
+
+impl Plugin for LobbyPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Lobby::default())
+            .add_systems(OnEnter(GameState::Lobby), spawn_lobby_screen)
+            .add_systems(OnExit(GameState::Lobby), despawn_lobby_screen)
+            .add_systems(
+                Update,
+                (
+                    (lobby_input, start_match).run_if(not_editing),
+                    update_lobby_screen,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Lobby)),
+            );
+    }
+}
+
+const MAX_PLAYERS: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SlotStage {
+    PickProfile,
+    PickFighter,
+    PickPalette,
+    Ready,
+}
+
+#[derive(Debug)]
+struct LobbySlot {
+    player_number: u8,
+    device: ControlDevice,
+    /// Index into the saved profiles; `None` plays as a guest.
+    profile: Option<usize>,
+    fighter: usize,
+    palette: usize,
+    stage: SlotStage,
+}
+
+/// Players who have claimed a slot. Kept between matches so a rematch starts with the same picks.
+#[derive(Debug, Default, Resource)]
+pub struct Lobby {
+    slots: Vec<LobbySlot>,
+}
+
+#[derive(Component)]
+struct LobbyScreen;
+
+#[derive(Component)]
+struct LobbySlotText(u8);
+
+#[derive(Component)]
+struct LobbyTitle;
+
+fn cycle(index: usize, len: usize, forward: bool) -> usize {
+    if forward {
+        (index + 1) % len
+    } else {
+        (index + len - 1) % len
+    }
+}
+
+fn lobby_input(
+    device_input: DeviceInput,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut lobby: ResMut<Lobby>,
+    mut save: ResMut<SaveData>,
+    mut previous: Local<HashMap<ControlDevice, InputSample>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Backspace) {
+        lobby.slots.clear();
+    }
+    if keyboard_input.just_pressed(KeyCode::Tab) {
+        save.video.palettes = save.video.palettes.next();
+    }
+    lobby.slots.retain(|slot| match slot.device {
+        ControlDevice::Gamepad(gamepad) => device_input.gamepads.contains(gamepad),
+        ControlDevice::Keyboard(_) => true,
+    });
+
+    let devices: Vec<ControlDevice> = save
+        .keyboard_layouts
+        .iter()
+        .map(|keys| ControlDevice::Keyboard(*keys))
+        .chain(device_input.gamepads.iter().map(ControlDevice::Gamepad))
+        .collect();
+
+    for device in devices {
+        let sample = device_input.sample(&device);
+        let last = previous.insert(device, sample).unwrap_or_default();
+        let attack = sample.attack && !last.attack;
+        let left = sample.left && !last.left;
+        let right = sample.right && !last.right;
+
+        let taken_profiles: Vec<usize> = lobby
+            .slots
+            .iter()
+            .filter(|slot| slot.device != device && slot.stage != SlotStage::PickProfile)
+            .filter_map(|slot| slot.profile)
+            .collect();
+        let Some(slot) = lobby.slots.iter_mut().find(|slot| slot.device == device) else {
+            if attack {
+                let Some(player_number) = (1..=MAX_PLAYERS)
+                    .find(|number| lobby.slots.iter().all(|slot| slot.player_number != *number))
+                else {
+                    continue;
+                };
+                lobby.slots.push(LobbySlot {
+                    player_number,
+                    device,
+                    profile: None,
+                    fighter: 0,
+                    palette: (player_number as usize - 1) % PALETTES.len(),
+                    stage: SlotStage::PickProfile,
+                });
+            }
+            continue;
+        };
+
+        match slot.stage {
+            SlotStage::PickProfile => {
+                if left || right {
+                    // The extra choice past the last profile is playing as a guest.
+                    let choices = save.profiles.len() + 1;
+                    let current = slot.profile.unwrap_or(save.profiles.len());
+                    let next = cycle(current, choices, right);
+                    slot.profile = (next < save.profiles.len()).then_some(next);
+                }
+                if attack
+                    && !slot
+                        .profile
+                        .is_some_and(|index| taken_profiles.contains(&index))
+                {
+                    if let Some(profile) = slot.profile.and_then(|index| save.profiles.get(index)) {
+                        slot.fighter = profile.fighter % FIGHTERS.len();
+                    }
+                    slot.stage = SlotStage::PickFighter;
+                }
+            }
+            SlotStage::PickFighter => {
+                if left || right {
+                    slot.fighter = cycle(slot.fighter, FIGHTERS.len(), right);
+                }
+                if attack {
+                    slot.stage = SlotStage::PickPalette;
+                }
+            }
+            SlotStage::PickPalette => {
+                if left || right {
+                    slot.palette = cycle(slot.palette, PALETTES.len(), right);
+                }
+                if attack {
+                    slot.stage = SlotStage::Ready;
+                }
+            }
+            SlotStage::Ready => {
+                if left || right {
+                    slot.stage = SlotStage::PickPalette;
+                }
+            }
+        }
+    }
+}
+
+fn start_match(
+    device_input: DeviceInput,
+    lobby: Res<Lobby>,
+    mut save: ResMut<SaveData>,
+    mut lineup: ResMut<Lineup>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let all_ready = lobby
+        .slots
+        .iter()
+        .all(|slot| slot.stage == SlotStage::Ready);
+    if lobby.slots.len() < 2 || !all_ready || !device_input.start_pressed() {
+        return;
+    }
+
+    lineup.0 = lobby
+        .slots
+        .iter()
+        .map(|slot| LineupEntry {
+            player_number: slot.player_number,
+            controls: Some(slot.device),
+            fighter: slot.fighter,
+            color: save.video.palettes.colors()[slot.palette],
+            palette: Some(slot.palette),
+            profile: slot
+                .profile
+                .and_then(|index| save.profiles.get(index))
+                .map(|profile| profile.name.clone()),
+        })
+        .collect();
+    lineup.0.sort_by_key(|entry| entry.player_number);
+
+    for slot in lobby.slots.iter() {
+        let Some(index) = slot.profile else {
+            continue;
+        };
+        if save
+            .profiles
+            .get(index)
+            .is_some_and(|profile| profile.fighter != slot.fighter)
+        {
+            save.profiles[index].fighter = slot.fighter;
+        }
+    }
+    next_state.set(GameState::Match);
+}
+
+fn spawn_lobby_screen(mut commands: Commands) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(40.0),
+                ..default()
+            },
+            background_color: Color::rgb(0.05, 0.05, 0.08).into(),
+            z_index: ZIndex::Global(10),
+            ..default()
+        })
+        .insert(LobbyScreen)
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle {
+                    text: Text {
+                        sections: vec![TextSection {
+                            value: String::new(),
+                            style: TextStyle {
+                                font_size: 40.0,
+                                color: Color::WHITE,
+                                font: Default::default(),
+                            },
+                        }],
+                        justify: JustifyText::Center,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(LobbyTitle);
+
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Percent(90.0),
+                        justify_content: JustifyContent::SpaceEvenly,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for player_number in 1..=MAX_PLAYERS {
+                        parent
+                            .spawn(TextBundle {
+                                text: Text {
+                                    sections: vec![
+                                        TextSection {
+                                            value: String::new(),
+                                            style: TextStyle {
+                                                font_size: 30.0,
+                                                color: Color::WHITE,
+                                                font: Default::default(),
+                                            },
+                                        },
+                                        TextSection {
+                                            value: String::new(),
+                                            style: TextStyle {
+                                                font_size: 30.0,
+                                                color: Color::WHITE,
+                                                font: Default::default(),
+                                            },
+                                        },
+                                    ],
+                                    justify: JustifyText::Left,
+                                    ..Default::default()
+                                },
+                                ..Default::default()
+                            })
+                            .insert(LobbySlotText(player_number));
+                    }
+                });
+        });
+}
+
+fn despawn_lobby_screen(mut commands: Commands, query: Query<Entity, With<LobbyScreen>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn device_name(device: &ControlDevice) -> String {
+    match device {
+        ControlDevice::Keyboard(keys) => {
+            format!(
+                "Keys {:?} {:?} {:?} {:?} {:?}",
+                keys.left, keys.attack, keys.right, keys.special, keys.throw
+            )
+        }
+        ControlDevice::Gamepad(gamepad) => format!("Gamepad {}", gamepad.id),
+    }
+}
+
+/// What sets a fighter apart, shown while it's being picked.
+fn fighter_card(fighter: &FighterDefinition) -> String {
+    let moves = &fighter.moves;
+    let specials: Vec<&str> = [
+        ("Reversal", true),
+        ("Strike", moves.long_strike.is_some()),
+        ("Shot", moves.projectile.is_some()),
+    ]
+    .into_iter()
+    .filter_map(|(name, has)| has.then_some(name))
+    .collect();
+    format!(
+        "{}\nSpeed {:.0}  Reach {:.0}\nPower x{:.2}  Push x{:.2}  Stun x{:.2}\n{}\n",
+        fighter.description,
+        fighter.movement.walk_speed,
+        fighter.combat.attack_range,
+        fighter.combat.damage_scale,
+        fighter.combat.pushback_scale,
+        fighter.combat.hitstun_scale,
+        specials.join(" "),
+    )
+}
+
+fn update_lobby_screen(
+    lobby: Res<Lobby>,
+    save: Res<SaveData>,
+    match_settings: Res<MatchSettings>,
+    mut title: Query<&mut Text, With<LobbyTitle>>,
+    mut slot_texts: Query<(&LobbySlotText, &mut Text), Without<LobbyTitle>>,
+) {
+    if let Ok(mut text) = title.get_single_mut() {
+        text.sections[0].value = format!(
+            "Press attack to join, left/right to pick, attack to confirm\n\
+             Enter or Start when everyone is ready  |  F5 clash rules: {:?}  |  F6 mode: {:?}  |  \
+             F2 friendly fire: {}  |  F12 round time: {}\n\
+             Backspace clears  |  F8 new profile  |  F9 profile stats  |  F10 tournament  |  \
+             Tab colors: {:?}",
+            match_settings.clash_preset,
+            match_settings.mode,
+            on_off(match_settings.friendly_fire),
+            match match_settings.round_seconds {
+                0 => "off".to_string(),
+                seconds => format!("{}s", seconds),
+            },
+            save.video.palettes,
+        );
+    }
+
+    for (slot_text, mut text) in slot_texts.iter_mut() {
+        let Some(slot) = lobby
+            .slots
+            .iter()
+            .find(|slot| slot.player_number == slot_text.0)
+        else {
+            text.sections[0].value = format!("Player {}\n\nPress attack\nto join", slot_text.0);
+            text.sections[1].value = String::new();
+            continue;
+        };
+
+        let status = match slot.stage {
+            SlotStage::PickProfile => "Pick a profile",
+            SlotStage::PickFighter => "Pick a fighter",
+            SlotStage::PickPalette => "Pick a color",
+            SlotStage::Ready => "READY",
+        };
+        let fighter = &FIGHTERS[slot.fighter];
+        let card = if slot.stage == SlotStage::PickFighter {
+            fighter_card(fighter)
+        } else {
+            String::new()
+        };
+        text.sections[0].value = format!(
+            "Player {}\n{}\n\nProfile: < {} >\nFighter: < {} >\n{}{}\n\nColor: ",
+            slot.player_number,
+            device_name(&slot.device),
+            slot.profile
+                .and_then(|index| save.profiles.get(index))
+                .map_or("Guest", |profile| profile.name.as_str()),
+            fighter.name,
+            card,
+            status,
+        );
+        text.sections[1].value = "######".to_string();
+        text.sections[1].style.color = save.video.palettes.colors()[slot.palette];
+    }
+}
@@ -1,8 +1,35 @@
-use std::time::Duration;
-
-use bevy::{prelude::*, render::camera::ScalingMode, utils::HashMap, window::PresentMode};
+use bevy::{
+    prelude::*,
+    render::camera::ScalingMode,
+    utils::{HashMap, HashSet},
+    window::PresentMode,
+};
 use bevy_tweening::TweeningPlugin;
+use serde::{Deserialize, Serialize};
+mod clash;
+mod combo;
+mod fighter;
+mod hud;
+mod input;
+mod input_history;
+mod lobby;
+mod pause;
 mod player;
+mod profile;
+mod projectile;
+mod replay;
+mod round;
+mod save;
+mod special;
+mod spectator;
+mod state_cue;
+mod state_machine;
+#[cfg(test)]
+mod test_support;
+mod theme;
+mod throw;
+mod tournament;
+mod training;
 
 fn main() {
     let window = Window {
@@ -23,18 +50,67 @@ fn main() {
             primary_window: Some(window),
             ..default()
         }))
+        .init_state::<GameState>()
+        .add_plugins(input::PlayerInputPlugin)
+        .add_plugins(save::SavePlugin)
+        .add_plugins(lobby::LobbyPlugin)
+        .add_plugins(profile::ProfilePlugin)
         .add_plugins(player::PlayerPlugin)
+        .add_plugins(combo::ComboPlugin)
+        .add_plugins(special::SpecialPlugin)
+        .add_plugins(projectile::ProjectilePlugin)
+        .add_plugins(throw::ThrowPlugin)
+        .add_plugins(training::TrainingPlugin)
+        .add_plugins(input_history::InputHistoryPlugin)
+        .add_plugins(replay::ReplayPlugin)
+        .add_plugins(spectator::SpectatorPlugin)
+        .add_plugins(tournament::TournamentPlugin)
+        .add_plugins(pause::PausePlugin)
+        .add_plugins(round::RoundPlugin)
+        .add_plugins(hud::HudPlugin)
+        .add_plugins(theme::ThemePlugin)
+        .add_plugins(state_cue::StateCuePlugin)
         .add_plugins(DevelopmentPlugin)
         .add_plugins(TweeningPlugin)
         .add_systems(Startup, setup)
-        .add_systems(Update, (reset_points, score_point, update_ui))
+        .add_systems(OnEnter(GameState::Match), reset_match)
+        .add_systems(
+            Update,
+            (
+                (reset_points, score_point)
+                    .run_if(in_state(GameState::Match))
+                    .run_if(pause::not_paused),
+                (
+                    cycle_clash_preset,
+                    cycle_match_mode,
+                    toggle_friendly_fire,
+                    cycle_round_time,
+                )
+                    .run_if(in_state(GameState::Lobby)),
+                apply_match_settings,
+            ),
+        )
+        .add_event::<RoundOver>()
         .register_type::<Controls>()
         .register_type::<Points>()
+        .register_type::<MatchSettings>()
         .insert_resource(Controls::default())
         .insert_resource(Points::default())
+        .insert_resource(MatchSettings::default())
         .run();
 }
 
+#[derive(States, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+enum GameState {
+    #[default]
+    Lobby,
+    Match,
+    /// Watching someone else's match; nothing local drives the fighters.
+    Spectate,
+    /// The tournament bracket, shown before it starts and between its matches.
+    Bracket,
+}
+
 struct DevelopmentPlugin;
 impl Plugin for DevelopmentPlugin {
     fn build(&self, _app: &mut App) {
@@ -43,11 +119,25 @@ impl Plugin for DevelopmentPlugin {
     }
 }
 
-#[derive(Debug, Reflect)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
 struct ControlPerPlayer {
     right: KeyCode,
     left: KeyCode,
     attack: KeyCode,
+    /// Missing from version 1 saves, which are given the default layouts' keys.
+    #[serde(default = "default_special_key")]
+    special: KeyCode,
+    /// Missing from version 1 and 2 saves, likewise.
+    #[serde(default = "default_throw_key")]
+    throw: KeyCode,
+}
+
+fn default_special_key() -> KeyCode {
+    KeyCode::ArrowUp
+}
+
+fn default_throw_key() -> KeyCode {
+    KeyCode::ShiftRight
 }
 
 impl Default for ControlPerPlayer {
@@ -56,66 +146,141 @@ impl Default for ControlPerPlayer {
             right: KeyCode::ArrowRight,
             left: KeyCode::ArrowLeft,
             attack: KeyCode::ArrowDown,
+            special: default_special_key(),
+            throw: default_throw_key(),
         }
     }
 }
 
+/// Where a player's input comes from: a set of keys on the shared keyboard, or a gamepad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+enum ControlDevice {
+    Keyboard(ControlPerPlayer),
+    Gamepad(Gamepad),
+}
+
 #[derive(Debug, Resource, Default, Reflect)]
 struct Controls {
-    control_map: HashMap<Entity, ControlPerPlayer>,
+    control_map: HashMap<Entity, ControlDevice>,
 }
 
-#[derive(Debug, Resource, Reflect, Clone)]
+#[derive(Debug, Default, Resource, Reflect, Clone)]
 #[reflect(Resource)]
 struct Points {
-    player_1: u32,
-    player_2: u32,
-    reset_timer: Timer,
+    scores: HashMap<Entity, u32>,
+    /// Rounds nobody won.
+    draws: u32,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Reflect, Serialize, Deserialize)]
+enum MatchMode {
+    /// Every fighter is on its own team.
+    #[default]
+    FreeForAll,
+    /// Odd player numbers against even ones.
+    Teams,
+}
+
+#[derive(Debug, Resource, Reflect)]
+#[reflect(Resource)]
+struct MatchSettings {
+    clash_preset: clash::ClashPreset,
+    mode: MatchMode,
+    friendly_fire: bool,
+    /// Length of the round clock; 0 means rounds only end by K.O.
+    round_seconds: u32,
 }
 
-impl Default for Points {
+impl Default for MatchSettings {
     fn default() -> Self {
-        let mut timer = Timer::from_seconds(3.0, TimerMode::Once);
-        timer.set_elapsed(Duration::from_secs(3));
+        MatchSettings {
+            clash_preset: clash::ClashPreset::default(),
+            mode: MatchMode::default(),
+            friendly_fire: false,
+            round_seconds: 60,
+        }
+    }
+}
+
+/// Round lengths F12 cycles through in the lobby.
+const ROUND_SECONDS_CHOICES: [u32; 4] = [30, 60, 99, 0];
 
-        Points {
-            player_1: 0,
-            player_2: 0,
-            reset_timer: timer,
+impl MatchSettings {
+    fn team_for(&self, player_number: u8) -> u8 {
+        match self.mode {
+            MatchMode::FreeForAll => player_number,
+            MatchMode::Teams => 2 - player_number % 2,
         }
     }
 }
 
-#[derive(Debug)]
-pub enum UIComponent {
-    PlayerOneScore,
-    ClashCounter,
-    PlayerTwoScore,
+fn cycle_match_mode(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut match_settings: ResMut<MatchSettings>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F6) {
+        match_settings.mode = match match_settings.mode {
+            MatchMode::FreeForAll => MatchMode::Teams,
+            MatchMode::Teams => MatchMode::FreeForAll,
+        };
+    }
 }
 
-#[derive(Debug, Component)]
-pub struct EditableUIComponent(pub UIComponent);
+fn toggle_friendly_fire(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut match_settings: ResMut<MatchSettings>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F2) {
+        match_settings.friendly_fire = !match_settings.friendly_fire;
+    }
+}
 
-fn update_ui(
-    mut query: Query<(&EditableUIComponent, &mut Text)>,
-    points: Res<Points>,
-    clash_counter: Res<player::ClashCounter>,
+fn cycle_round_time(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut match_settings: ResMut<MatchSettings>,
 ) {
-    for (component, mut text) in query.iter_mut() {
-        match component.0 {
-            UIComponent::PlayerOneScore => {
-                text.sections[0].value = format!("Player 1: {}", points.player_1);
-            }
-            UIComponent::ClashCounter => {
-                text.sections[0].value = format!("Clash Counter: {}", clash_counter.0);
-            }
-            UIComponent::PlayerTwoScore => {
-                text.sections[0].value = format!("Player 2: {}", points.player_2);
-            }
-        }
+    if keyboard_input.just_pressed(KeyCode::F12) {
+        let index = ROUND_SECONDS_CHOICES
+            .iter()
+            .position(|seconds| *seconds == match_settings.round_seconds)
+            .unwrap_or(0);
+        match_settings.round_seconds =
+            ROUND_SECONDS_CHOICES[(index + 1) % ROUND_SECONDS_CHOICES.len()];
+    }
+}
+
+fn reset_match(mut points: ResMut<Points>, mut clash_counter: ResMut<player::ClashCounter>) {
+    *points = Points::default();
+    clash_counter.0 = 0;
+}
+
+fn cycle_clash_preset(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut match_settings: ResMut<MatchSettings>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F5) {
+        match_settings.clash_preset = match_settings.clash_preset.next();
+    }
+}
+
+fn apply_match_settings(
+    match_settings: Res<MatchSettings>,
+    mut clash_rules: ResMut<clash::ClashRules>,
+) {
+    if match_settings.is_changed() {
+        *clash_rules = match_settings.clash_preset.rules();
     }
 }
 
+/// Sent once when a round is decided. No winners means a draw.
+#[derive(Event)]
+struct RoundOver {
+    winners: Vec<Entity>,
+    losers: Vec<Entity>,
+    /// Decided by the judges when the clock ran out, rather than by a K.O.
+    time_over: bool,
+}
+
 fn reset_points(
     keyboard_input: Res<ButtonInput<KeyCode>>,
     mut ev_reset: EventWriter<player::ResetPlayers>,
@@ -123,8 +288,8 @@ fn reset_points(
     mut clash_counter: ResMut<player::ClashCounter>,
 ) {
     if keyboard_input.just_pressed(KeyCode::KeyU) {
-        points.player_1 = 0;
-        points.player_2 = 0;
+        points.scores.clear();
+        points.draws = 0;
         clash_counter.0 = 0;
         ev_reset.send(player::ResetPlayers);
     }
@@ -139,143 +304,96 @@ fn setup(mut commands: Commands) {
         },
         ..default()
     };
-    let camera = commands.spawn(camera_bundle).id();
-
-    commands
-        .spawn(NodeBundle {
-            style: Style {
-                width: Val::Percent(80.0),
-                height: Val::Percent(80.0),
-                justify_content: JustifyContent::SpaceBetween,
-                flex_direction: FlexDirection::Column,
-                justify_self: JustifySelf::Center,
-                align_self: AlignSelf::Center,
-                ..default()
-            },
-            ..default()
-        })
-        .with_children(|parent| {
-            parent
-                .spawn(NodeBundle {
-                    style: Style {
-                        width: Val::Percent(100.),
-                        height: Val::Percent(50.),
-                        justify_content: JustifyContent::SpaceBetween,
-                        ..default()
-                    },
-                    ..default()
-                })
-                .with_children(|parent| {
-                    parent
-                        .spawn(TextBundle {
-                            text: Text {
-                                sections: vec![TextSection {
-                                    value: "Player 1: 0".to_string(),
-                                    style: TextStyle {
-                                        font_size: 40.0,
-                                        color: Color::WHITE,
-                                        font: Default::default(),
-                                    },
-                                }],
-                                justify: JustifyText::Left,
-                                ..Default::default()
-                            },
-                            ..Default::default()
-                        })
-                        .insert(EditableUIComponent(UIComponent::PlayerOneScore));
-
-                    parent
-                        .spawn(TextBundle {
-                            text: Text {
-                                sections: vec![TextSection {
-                                    value: "Clash Counter: 0".to_string(),
-                                    style: TextStyle {
-                                        font_size: 40.0,
-                                        color: Color::WHITE,
-                                        font: Default::default(),
-                                    },
-                                }],
-                                justify: JustifyText::Center,
-                                ..Default::default()
-                            },
-                            ..Default::default()
-                        })
-                        .insert(EditableUIComponent(UIComponent::ClashCounter));
-
-                    parent
-                        .spawn(TextBundle {
-                            text: Text {
-                                sections: vec![TextSection {
-                                    value: "Player 2: 0".to_string(),
-                                    style: TextStyle {
-                                        font_size: 40.0,
-                                        color: Color::WHITE,
-                                        font: Default::default(),
-                                    },
-                                }],
-                                justify: JustifyText::Right,
-                                ..Default::default()
-                            },
-                            ..Default::default()
-                        })
-                        .insert(EditableUIComponent(UIComponent::PlayerTwoScore));
-                });
+    commands.spawn(camera_bundle);
+}
 
-            parent
-                .spawn(NodeBundle {
-                    style: Style {
-                        width: Val::Percent(100.),
-                        height: Val::Percent(50.),
-                        justify_content: JustifyContent::End,
-                        align_items: AlignItems::Baseline,
-                        flex_direction: FlexDirection::ColumnReverse,
-                        ..default()
-                    },
-                    ..default()
-                })
-                .with_children(|parent| {
-                    parent.spawn(TextBundle {
-                        text: Text {
-                            sections: vec![TextSection {
-                                value: "Press U to reset points".to_string(),
-                                style: TextStyle {
-                                    font_size: 40.0,
-                                    color: Color::WHITE,
-                                    font: Default::default(),
-                                },
-                            }],
-                            justify: JustifyText::Center,
-                            ..Default::default()
-                        },
-                        ..Default::default()
-                    });
-                });
-        })
-        .insert(TargetCamera(camera));
+/// Time over: the team with the most fighters standing wins, then the one with the most
+/// health left, then the one that landed the most hits, then the one that parried most.
+/// Anything still level is a draw.
+fn judge_time_over(
+    players: &Query<(Entity, &player::Player)>,
+    stats: &round::RoundStats,
+) -> Option<u8> {
+    let mut teams: HashMap<u8, (u32, u32, u32, u32)> = HashMap::default();
+    for (entity, player) in players.iter() {
+        let team = teams.entry(player.team).or_default();
+        if player.state != player::PlayerState::Dead {
+            team.0 += 1;
+            team.1 += player.health.round() as u32;
+        }
+        team.2 += stats.hits(entity);
+        team.3 += stats.parries(entity);
+    }
+    let best = teams.values().max()?;
+    let mut leaders = teams.iter().filter(|(_, score)| *score == best);
+    let (team, _) = leaders.next()?;
+    leaders.next().is_none().then_some(*team)
 }
 
 fn score_point(
     mut ev_player_state_change: EventReader<player::PlayerStateChangeEvent>,
     mut points: ResMut<Points>,
-    query: Query<&mut player::Player>,
-    time: Res<Time>,
-    mut ev_reset: EventWriter<player::ResetPlayers>,
+    query: Query<(Entity, &player::Player)>,
+    round: Res<round::RoundSequence>,
+    round_clock: Res<round::RoundClock>,
+    round_stats: Res<round::RoundStats>,
+    mut ev_round_over: EventWriter<RoundOver>,
 ) {
-    points.reset_timer.tick(time.delta());
-    if points.reset_timer.just_finished() {
-        println!("Resetting game");
-        ev_reset.send(player::ResetPlayers);
-    }
+    // Once a round is decided, later deaths (even in the same frame) don't decide it again.
+    let mut live = round.is_live();
     for event in ev_player_state_change.read() {
         if event.1 == player::PlayerState::Dead {
-            points.reset_timer.reset();
-            if let Ok(player) = query.get(event.0) {
-                match player.player_number {
-                    1 => points.player_2 += 1,
-                    2 => points.player_1 += 1,
-                    _ => unreachable!("Invalid player number"),
+            let Ok((_, player)) = query.get(event.0) else {
+                continue;
+            };
+            if let Some(killer) = player.last_hit_by {
+                if query
+                    .get(killer)
+                    .is_ok_and(|(_, killer)| killer.team != player.team)
+                {
+                    *points.scores.entry(killer).or_default() += 1;
                 }
             }
+
+            let teams_standing: HashSet<u8> = query
+                .iter()
+                .filter(|(_, player)| player.state != player::PlayerState::Dead)
+                .map(|(_, player)| player.team)
+                .collect();
+            if teams_standing.len() <= 1 && live {
+                live = false;
+                let (winners, losers) = query
+                    .iter()
+                    .partition::<Vec<_>, _>(|(_, player)| teams_standing.contains(&player.team));
+                if winners.is_empty() {
+                    points.draws += 1;
+                }
+                ev_round_over.send(RoundOver {
+                    winners: winners.into_iter().map(|(entity, _)| entity).collect(),
+                    losers: losers.into_iter().map(|(entity, _)| entity).collect(),
+                    time_over: false,
+                });
+            }
+        }
+    }
+
+    if live && round_clock.expired() {
+        let winning_team = judge_time_over(&query, &round_stats);
+        let (winners, losers) = query
+            .iter()
+            .partition::<Vec<_>, _>(|(_, player)| Some(player.team) == winning_team);
+        // Nobody was knocked out, so the point goes to whoever on the winning team hit most.
+        match winners
+            .iter()
+            .max_by_key(|(entity, _)| round_stats.hits(*entity))
+        {
+            Some((entity, _)) => *points.scores.entry(*entity).or_default() += 1,
+            None => points.draws += 1,
         }
+        ev_round_over.send(RoundOver {
+            winners: winners.into_iter().map(|(entity, _)| entity).collect(),
+            losers: losers.into_iter().map(|(entity, _)| entity).collect(),
+            time_over: true,
+        });
     }
 }
@@ -0,0 +1,330 @@
+use bevy::prelude::*;
+
+use crate::{
+    input::{InputSet, PlayerInput},
+    player::ResetPlayers,
+    save::SaveData,
+    tournament::Tournament,
+    GameState,
+};
+
+/// Esc (or Start) during a match. Pausing stops virtual time, which is what every
+/// `Timer` and tween in the match ticks on, and gameplay systems don't run while
+/// the menu is open, so a paused round plays out exactly as an unpaused one.
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PauseMenu::default())
+            .add_systems(OnExit(GameState::Match), close_pause_menu)
+            .add_systems(
+                Update,
+                (
+                    (pause_menu_input, update_pause_menu)
+                        .chain()
+                        .before(InputSet::Sample),
+                    settle_inputs_after_resume
+                        .after(InputSet::Sample)
+                        .before(InputSet::Override),
+                )
+                    .run_if(in_state(GameState::Match)),
+            );
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum PausePage {
+    #[default]
+    Main,
+    Settings,
+}
+
+#[derive(Debug, Default, Resource)]
+pub struct PauseMenu {
+    open: bool,
+    page: PausePage,
+    cursor: usize,
+    /// Closed this frame; buttons still held from the menu aren't presses.
+    resumed: bool,
+}
+
+pub fn not_paused(menu: Res<PauseMenu>) -> bool {
+    !menu.open
+}
+
+const MAIN_ITEMS: usize = 4;
+const SETTINGS_ITEMS: usize = 6;
+/// UI scale choices, in percent.
+const UI_SCALES: [u32; 4] = [75, 100, 125, 150];
+
+#[derive(Component)]
+struct PauseOverlay;
+
+#[derive(Component)]
+struct PauseOverlayText;
+
+fn set_paused(
+    commands: &mut Commands,
+    menu: &mut PauseMenu,
+    time: &mut Time<Virtual>,
+    overlays: &Query<Entity, With<PauseOverlay>>,
+    paused: bool,
+) {
+    menu.open = paused;
+    menu.resumed = !paused;
+    menu.page = PausePage::Main;
+    menu.cursor = 0;
+    if paused {
+        time.pause();
+        spawn_pause_overlay(commands);
+    } else {
+        time.unpause();
+        for entity in overlays.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn pause_menu_input(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    mut menu: ResMut<PauseMenu>,
+    mut time: ResMut<Time<Virtual>>,
+    overlays: Query<Entity, With<PauseOverlay>>,
+    mut save: ResMut<SaveData>,
+    mut ev_reset: EventWriter<ResetPlayers>,
+    tournament: Res<Tournament>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let gamepad_pressed = |button| {
+        gamepads
+            .iter()
+            .any(|gamepad| gamepad_buttons.just_pressed(GamepadButton::new(gamepad, button)))
+    };
+    let toggle =
+        keyboard_input.just_pressed(KeyCode::Escape) || gamepad_pressed(GamepadButtonType::Start);
+
+    if !menu.open {
+        if toggle {
+            set_paused(&mut commands, &mut menu, &mut time, &overlays, true);
+        }
+        return;
+    }
+
+    let up =
+        keyboard_input.just_pressed(KeyCode::ArrowUp) || gamepad_pressed(GamepadButtonType::DPadUp);
+    let down = keyboard_input.just_pressed(KeyCode::ArrowDown)
+        || gamepad_pressed(GamepadButtonType::DPadDown);
+    let confirm =
+        keyboard_input.just_pressed(KeyCode::Enter) || gamepad_pressed(GamepadButtonType::South);
+    let back = toggle || gamepad_pressed(GamepadButtonType::East);
+
+    let items = match menu.page {
+        PausePage::Main => MAIN_ITEMS,
+        PausePage::Settings => SETTINGS_ITEMS,
+    };
+    if up {
+        menu.cursor = (menu.cursor + items - 1) % items;
+    }
+    if down {
+        menu.cursor = (menu.cursor + 1) % items;
+    }
+
+    match menu.page {
+        PausePage::Main => {
+            if back {
+                set_paused(&mut commands, &mut menu, &mut time, &overlays, false);
+                return;
+            }
+            if !confirm {
+                return;
+            }
+            match menu.cursor {
+                0 => set_paused(&mut commands, &mut menu, &mut time, &overlays, false),
+                1 => {
+                    ev_reset.send(ResetPlayers);
+                    set_paused(&mut commands, &mut menu, &mut time, &overlays, false);
+                }
+                2 => {
+                    menu.page = PausePage::Settings;
+                    menu.cursor = 0;
+                }
+                _ => {
+                    set_paused(&mut commands, &mut menu, &mut time, &overlays, false);
+                    if tournament.is_running() {
+                        next_state.set(GameState::Bracket);
+                    } else {
+                        next_state.set(GameState::Lobby);
+                    }
+                }
+            }
+        }
+        PausePage::Settings => {
+            if back || (confirm && menu.cursor == SETTINGS_ITEMS - 1) {
+                menu.page = PausePage::Main;
+                menu.cursor = 2;
+                return;
+            }
+            let change = confirm
+                || keyboard_input.just_pressed(KeyCode::ArrowLeft)
+                || keyboard_input.just_pressed(KeyCode::ArrowRight);
+            if !change {
+                return;
+            }
+            match menu.cursor {
+                0 => save.video.vsync = !save.video.vsync,
+                1 => save.video.fullscreen = !save.video.fullscreen,
+                2 => save.video.theme = save.video.theme.next(),
+                3 => save.video.palettes = save.video.palettes.next(),
+                _ => {
+                    let percent = (save.video.ui_scale * 100.0).round() as u32;
+                    let index = UI_SCALES
+                        .iter()
+                        .position(|scale| *scale == percent)
+                        .unwrap_or(0);
+                    save.video.ui_scale = UI_SCALES[(index + 1) % UI_SCALES.len()] as f32 / 100.0;
+                }
+            }
+        }
+    }
+}
+
+/// Confirming with South, which is also attack, would otherwise attack on the way out:
+/// inputs weren't sampled while paused, so the last sample is from before the pause.
+fn settle_inputs_after_resume(mut menu: ResMut<PauseMenu>, mut inputs: Query<&mut PlayerInput>) {
+    if !menu.resumed {
+        return;
+    }
+    menu.resumed = false;
+    for mut input in inputs.iter_mut() {
+        input.previous = input.current;
+    }
+}
+
+fn close_pause_menu(
+    mut commands: Commands,
+    mut menu: ResMut<PauseMenu>,
+    mut time: ResMut<Time<Virtual>>,
+    overlays: Query<Entity, With<PauseOverlay>>,
+) {
+    if menu.open {
+        set_paused(&mut commands, &mut menu, &mut time, &overlays, false);
+    }
+}
+
+fn spawn_pause_overlay(commands: &mut Commands) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.7).into(),
+            z_index: ZIndex::Global(15),
+            ..default()
+        })
+        .insert(PauseOverlay)
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle {
+                    text: Text {
+                        sections: vec![TextSection {
+                            value: String::new(),
+                            style: TextStyle {
+                                font_size: 40.0,
+                                color: Color::WHITE,
+                                font: Default::default(),
+                            },
+                        }],
+                        justify: JustifyText::Left,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(PauseOverlayText);
+        });
+}
+
+pub fn on_off(value: bool) -> &'static str {
+    if value {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+fn update_pause_menu(
+    menu: Res<PauseMenu>,
+    save: Res<SaveData>,
+    mut text: Query<&mut Text, With<PauseOverlayText>>,
+) {
+    let Ok(mut text) = text.get_single_mut() else {
+        return;
+    };
+    let (title, items) = match menu.page {
+        PausePage::Main => (
+            "PAUSED",
+            vec![
+                "Resume".to_string(),
+                "Restart round".to_string(),
+                "Settings".to_string(),
+                "Quit to menu".to_string(),
+            ],
+        ),
+        PausePage::Settings => (
+            "SETTINGS",
+            vec![
+                format!("VSync: {}", on_off(save.video.vsync)),
+                format!("Fullscreen: {}", on_off(save.video.fullscreen)),
+                format!("Theme: {:?}", save.video.theme),
+                format!("Colors: {:?}", save.video.palettes),
+                format!("UI scale: {:.0}%", save.video.ui_scale * 100.0),
+                "Back".to_string(),
+            ],
+        ),
+    };
+
+    let mut value = format!("{}\n\n", title);
+    for (index, item) in items.iter().enumerate() {
+        let marker = if index == menu.cursor { ">" } else { " " };
+        value.push_str(&format!("{} {}\n", marker, item));
+    }
+    if text.sections[0].value != value {
+        text.sections[0].value = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::InputSample;
+
+    #[test]
+    fn a_button_held_from_the_menu_is_not_a_press() {
+        let mut app = App::new();
+        app.insert_resource(PauseMenu {
+            resumed: true,
+            ..default()
+        })
+        .add_systems(Update, settle_inputs_after_resume);
+        let attack = InputSample {
+            attack: true,
+            ..default()
+        };
+        let mut input = PlayerInput::default();
+        input.push(attack);
+        let fighter = app.world.spawn(input).id();
+
+        app.update();
+        let input = app.world.get::<PlayerInput>(fighter).unwrap();
+        assert!(!input.attack_just_pressed());
+        assert!(!app.world.resource::<PauseMenu>().resumed);
+    }
+}
@@ -3,10 +3,23 @@ use std::time::Duration;
 use bevy::{
     prelude::*,
     sprite::{MaterialMesh2dBundle, Mesh2dHandle},
+    utils::HashMap,
 };
 use bevy_tweening::{lens::TransformPositionLens, Animator, EaseFunction, Tween};
+use serde::{Deserialize, Serialize};
 
-use crate::Controls;
+use crate::{
+    clash::{ClashReset, ClashRules},
+    combo::{ComboEnded, ComboHit, ComboRules},
+    fighter::{CombatStats, FighterDefinition, FIGHTERS},
+    input::{InputBuffer, InputBufferSettings, InputSet, PlayerInput},
+    pause::not_paused,
+    profile::PlayerProfile,
+    save::SaveData,
+    state_cue::spawn_state_cues,
+    state_machine::PlayerStateMachine,
+    ControlDevice, Controls, GameState, MatchSettings,
+};
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
@@ -17,37 +30,120 @@ impl Plugin for PlayerPlugin {
             .add_event::<ResetPlayers>()
             .register_type::<Player>()
             .register_type::<ClashCounter>()
+            .register_type::<StartingDistance>()
+            .register_type::<ClashRules>()
+            .register_type::<PlayerSlots>()
+            .register_type::<Lineup>()
             .insert_resource(ClashCounter::default())
-            .add_systems(Startup, (spawn_players, reset_player))
+            .insert_resource(StartingDistance::default())
+            .insert_resource(ClashRules::default())
+            .insert_resource(PlayerSlots::default())
+            .insert_resource(Lineup::default())
+            .add_systems(OnEnter(GameState::Match), spawn_players)
+            .add_systems(OnExit(GameState::Match), despawn_players)
+            .add_systems(OnEnter(GameState::Spectate), spawn_players)
+            .add_systems(OnExit(GameState::Spectate), despawn_players)
             .add_systems(
                 Update,
                 (
                     player_timer_update,
                     reset_player_function,
-                    move_player,
+                    move_player.after(InputSet::Override),
                     reset_player,
                     check_attack_hit,
-                    update_player_color,
                     clash_players,
                     push_back_player_with_clash,
-                ),
-            );
+                )
+                    .run_if(in_state(GameState::Match))
+                    .run_if(not_paused),
+            )
+            .add_systems(
+                Update,
+                (recolor_players, update_player_color)
+                    .chain()
+                    .run_if(in_state(GameState::Match).or_else(in_state(GameState::Spectate))),
+            )
+            .add_systems(Update, release_despawned_slots);
     }
 }
 
+/// Timers are stored in seconds; anything shown or configured in frames uses 60 Hz frames.
+pub const FRAMES_PER_SECOND: f32 = 60.0;
+
+pub fn secs_to_frames(secs: f32) -> u32 {
+    (secs * FRAMES_PER_SECOND).ceil() as u32
+}
+
+pub fn frames_to_secs(frames: u32) -> f32 {
+    frames as f32 / FRAMES_PER_SECOND
+}
+
 #[derive(Resource, Default, Reflect)]
 pub struct ClashCounter(pub u32);
 
+/// Which fighter is in each player slot. Slot numbers are `Player::player_number`.
+#[derive(Resource, Default, Debug, Reflect)]
+#[reflect(Resource)]
+pub struct PlayerSlots(HashMap<u8, Entity>);
+
+impl PlayerSlots {
+    pub fn get(&self, slot: u8) -> Option<Entity> {
+        self.0.get(&slot).copied()
+    }
+
+    pub fn insert(&mut self, slot: u8, entity: Entity) {
+        if let Some(previous) = self.0.insert(slot, entity) {
+            warn!(
+                "Player slot {} taken over from {:?} by {:?}",
+                slot, previous, entity
+            );
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    /// Occupied slots in slot order.
+    pub fn iter(&self) -> impl Iterator<Item = (u8, Entity)> {
+        let mut slots: Vec<_> = self
+            .0
+            .iter()
+            .map(|(slot, entity)| (*slot, *entity))
+            .collect();
+        slots.sort();
+        slots.into_iter()
+    }
+}
+
+/// Distance between the outermost fighters when a round is reset.
+#[derive(Resource, Reflect)]
+pub struct StartingDistance(pub f32);
+
+impl Default for StartingDistance {
+    fn default() -> Self {
+        StartingDistance(600.0)
+    }
+}
+
 #[derive(Event)]
-pub struct AttackEvent(Entity);
+pub struct AttackEvent {
+    pub attacker: Entity,
+    /// Where the hit comes from, if not the attacker, like a projectile.
+    pub origin: Option<Vec3>,
+    /// How close a fighter has to be to get hit.
+    pub range: f32,
+    /// Lands straight away, with no parry window.
+    pub unparryable: bool,
+}
 
 #[derive(Event)]
 pub struct PlayerStateChangeEvent(pub Entity, pub PlayerState);
 
 #[derive(Event)]
-struct ClashEvent(Entity, Entity);
+pub struct ClashEvent(Entity, Entity);
 
-#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Reflect)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Reflect, Serialize, Deserialize)]
 pub enum PlayerState {
     #[default]
     Alive,
@@ -55,34 +151,133 @@ pub enum PlayerState {
     TakingDamage,
     Clashing,
     Wiff,
+    /// Hit and unable to act; further hits before it wears off are a combo.
+    Hitstun,
+    /// Invulnerable special move that hits everyone close by.
+    Reversal,
+    /// Special move reaching much further than an attack, with a long recovery.
+    LongStrike,
+    /// Recovering from throwing a projectile.
+    Firing,
+    /// Holding a fighter that was grabbed.
+    Throwing,
+    /// Grabbed, with a short window to tech before the throw lands.
+    Thrown,
+    /// Broke free of a throw, or had theirs broken; both fighters are pushed apart.
+    Teching,
+    /// Double tap forward. Holding forward afterwards keeps the fighter running.
+    Dashing,
+    /// Double tap back, invulnerable for the first few frames.
+    Backdashing,
 }
 
 #[derive(Debug, Component, Reflect)]
 pub struct Player {
     pub player_number: u8,
+    /// Index into [`FIGHTERS`].
+    pub fighter: usize,
+    /// Fighters on the same team don't score off each other and only hit each
+    /// other with friendly fire on.
+    pub team: u8,
     pub state: PlayerState,
     pub color_mesh_handle: Handle<ColorMaterial>,
     pub attack_timer: Timer,
     //200ms timer
     pub parry_timer: Timer,
     pub clashing_timer: Timer,
+    pub hitstun_timer: Timer,
+    /// Runs while a special move plays out.
+    pub special_timer: Timer,
+    /// The tech window while `Thrown`, otherwise how long `Throwing` or `Teching` lasts.
+    pub throw_timer: Timer,
+    pub dash_timer: Timer,
+    /// Units per second along x; carries over from a dash into walking.
+    pub velocity: f32,
+    /// Dashed forward and has held forward since.
+    pub running: bool,
+    pub health: f32,
+    /// Spent on special moves. Carries over between rounds.
+    pub meter: f32,
+    /// Hits and damage taken in the current combo.
+    pub combo_hits: u32,
+    pub combo_damage: f32,
     pub color: Color,
+    /// Slot in the chosen [`crate::fighter::PaletteSet`] `color` came from.
+    pub palette: Option<usize>,
+    /// Who put this fighter into `TakingDamage` last; they get the point if it dies.
+    pub last_hit_by: Option<Entity>,
 }
 
 impl Default for Player {
     fn default() -> Self {
         Player {
             player_number: 0,
+            fighter: 0,
+            team: 0,
             state: PlayerState::Alive,
             color_mesh_handle: Default::default(),
             attack_timer: Timer::from_seconds(1.0, TimerMode::Once),
             parry_timer: Timer::from_seconds(0.016 * 4.0, TimerMode::Once),
             clashing_timer: Timer::from_seconds(1.0, TimerMode::Once),
+            hitstun_timer: Timer::from_seconds(0.0, TimerMode::Once),
+            special_timer: Timer::from_seconds(0.0, TimerMode::Once),
+            throw_timer: Timer::from_seconds(0.0, TimerMode::Once),
+            dash_timer: Timer::from_seconds(0.0, TimerMode::Once),
+            velocity: 0.0,
+            running: false,
+            health: 100.0,
+            meter: 0.0,
+            combo_hits: 0,
+            combo_damage: 0.0,
             color: Color::rgb(1.0, 0.7, 0.6),
+            palette: None,
+            last_hit_by: None,
         }
     }
 }
 
+impl Player {
+    pub fn definition(&self) -> &'static FighterDefinition {
+        &FIGHTERS[self.fighter % FIGHTERS.len()]
+    }
+
+    /// Nothing can hit or grab the fighter: all through a reversal, and at the start of
+    /// a backdash.
+    pub fn invulnerable(&self) -> bool {
+        match self.state {
+            PlayerState::Reversal => true,
+            PlayerState::Backdashing => {
+                let frames = self.definition().movement.backdash_invulnerable_frames;
+                self.dash_timer.elapsed_secs() < frames_to_secs(frames)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The closest fighter `entity` can hit, out of `fighters` given as entity, team and x.
+pub fn nearest_opponent_x(
+    fighters: &[(Entity, u8, f32)],
+    entity: Entity,
+    team: u8,
+    x: f32,
+    friendly_fire: bool,
+) -> Option<f32> {
+    fighters
+        .iter()
+        .filter(|(other, other_team, _)| *other != entity && (friendly_fire || *other_team != team))
+        .map(|(.., other_x)| *other_x)
+        .min_by(|a, b| (a - x).abs().total_cmp(&(b - x).abs()))
+}
+
+/// Which way is forward: towards the nearest opponent, or right if there is none.
+pub fn facing(x: f32, opponent_x: Option<f32>) -> f32 {
+    match opponent_x {
+        Some(opponent_x) if opponent_x < x => -1.0,
+        _ => 1.0,
+    }
+}
+
 fn reset_player(
     keyboard_input: Res<ButtonInput<KeyCode>>,
     mut ev_reset: EventWriter<ResetPlayers>,
@@ -94,86 +289,212 @@ fn reset_player(
 #[derive(Event)]
 pub struct ResetPlayers;
 
+#[allow(clippy::too_many_arguments)]
 fn reset_player_function(
     mut ev_reset: EventReader<ResetPlayers>,
     mut query: Query<(&mut Player, Entity, &mut Transform)>,
-    mut ev_player_state_change: EventWriter<PlayerStateChangeEvent>,
+    mut state_machine: PlayerStateMachine,
+    starting_distance: Res<StartingDistance>,
+    clash_rules: Res<ClashRules>,
+    combo_rules: Res<ComboRules>,
+    mut clash_counter: ResMut<ClashCounter>,
+    slots: Res<PlayerSlots>,
 ) {
     for _ in ev_reset.read() {
-        let mut p1 = None;
-        let mut p2 = None;
-        for p in query.iter_mut() {
-            if p.0.player_number == 1 {
-                p1 = Some(p);
-            } else {
-                p2 = Some(p);
-            }
+        if clash_rules.reset == ClashReset::PerRound {
+            clash_counter.0 = 0;
         }
-        if p1.is_none() || p2.is_none() {
-            return;
+        let mut players: Vec<_> = query
+            .iter_many(slots.iter().map(|(_, entity)| entity))
+            .map(|(player, entity, _)| (player.team, player.player_number, entity))
+            .collect();
+        players.sort();
+        let count = players.len();
+        for (index, (.., entity)) in players.into_iter().enumerate() {
+            let Ok((mut player, entity, mut transform)) = query.get_mut(entity) else {
+                continue;
+            };
+            transform.translation.x = starting_x(index, count, starting_distance.0);
+            let duration = player.attack_timer.duration();
+            player.attack_timer.set_elapsed(duration);
+            player.last_hit_by = None;
+            player.health = combo_rules.max_health;
+            player.velocity = 0.0;
+            player.running = false;
+            state_machine.reset(entity, &mut player);
         }
-        let (mut player1, entity1, mut t1) = p1.unwrap();
-        let (mut player2, entity2, mut t2) = p2.unwrap();
-        player1.state = PlayerState::Alive;
-        t1.translation.x = -300.0;
-        t2.translation.x = 300.0;
-        player2.state = PlayerState::Alive;
-        let duration = player1.attack_timer.duration();
-        player1.attack_timer.set_elapsed(duration);
-        player2.attack_timer.set_elapsed(duration);
-        ev_player_state_change.send(PlayerStateChangeEvent(entity1, PlayerState::Alive));
-        ev_player_state_change.send(PlayerStateChangeEvent(entity2, PlayerState::Alive));
     }
 }
 
+/// Spreads `count` fighters evenly across `distance`, centred on the origin.
+fn starting_x(index: usize, count: usize, distance: f32) -> f32 {
+    if count < 2 {
+        return 0.0;
+    }
+    -distance / 2.0 + distance * index as f32 / (count - 1) as f32
+}
+
+/// Moves `current` towards `target` by no more than `max_step`.
+fn approach(current: f32, target: f32, max_step: f32) -> f32 {
+    current + (target - current).clamp(-max_step, max_step)
+}
+
+#[allow(clippy::too_many_arguments)]
 fn move_player(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    controls: Res<super::Controls>,
-    mut query: Query<(&mut Player, &mut Transform, Entity)>,
+    mut query: Query<(
+        &mut Player,
+        &mut Transform,
+        &PlayerInput,
+        &mut InputBuffer,
+        Entity,
+    )>,
     mut ev_attack: EventWriter<AttackEvent>,
+    mut state_machine: PlayerStateMachine,
+    buffer_settings: Res<InputBufferSettings>,
     clash_counter: Res<ClashCounter>,
+    clash_rules: Res<ClashRules>,
+    match_settings: Res<MatchSettings>,
     camera_query: Query<(&Camera, &OrthographicProjection), With<Transform>>,
     time: Res<Time>,
 ) {
-    let move_amount: f32 = 650.0 * time.delta_seconds();
+    let delta = time.delta_seconds();
+    let clash_speed = clash_rules.speed_bonus.at(clash_counter.0);
+    let double_tap = frames_to_secs(buffer_settings.double_tap_frames);
 
     if let Some((_, ortho_proj)) = camera_query.iter().find(|cam| cam.0.is_active).take() {
         let bounds = ortho_proj.area;
+        let fighters: Vec<(Entity, u8, f32)> = query
+            .iter()
+            .filter(|(player, ..)| player.state != PlayerState::Dead)
+            .map(|(player, transform, .., entity)| (entity, player.team, transform.translation.x))
+            .collect();
 
-        for (mut player, mut transform, entity) in query.iter_mut() {
-            if let Some(controls) = controls.control_map.get(&entity) {
-                if matches!(player.state, PlayerState::Alive | PlayerState::TakingDamage) {
-                    if keyboard_input.pressed(controls.right) {
-                        transform.translation.x += move_amount + (clash_counter.0 as f32);
-                    }
-                    if keyboard_input.pressed(controls.left) {
-                        transform.translation.x += -(move_amount + (clash_counter.0 as f32));
+        for (mut player, mut transform, input, mut buffer, entity) in query.iter_mut() {
+            let stats = &player.definition().movement;
+            let x = transform.translation.x;
+            let forward = facing(
+                x,
+                nearest_opponent_x(
+                    &fighters,
+                    entity,
+                    player.team,
+                    x,
+                    match_settings.friendly_fire,
+                ),
+            );
+            let held = (input.current.right as i32 - input.current.left as i32) as f32;
+
+            let buffer = &mut *buffer;
+            let mut dash = None;
+            for (pressed, last_tap, direction) in [
+                (input.left_just_pressed(), &mut buffer.left_tap, -1.0),
+                (input.right_just_pressed(), &mut buffer.right_tap, 1.0),
+            ] {
+                *last_tap = last_tap
+                    .map(|age| age + delta)
+                    .filter(|age| *age <= double_tap);
+                if pressed {
+                    if last_tap.take().is_some() {
+                        dash = Some(direction);
+                    } else {
+                        *last_tap = Some(0.0);
                     }
                 }
-                if matches!(player.state, PlayerState::Wiff) {
-                    if keyboard_input.pressed(controls.right) {
-                        transform.translation.x += move_amount * 0.1;
-                    }
-                    if keyboard_input.pressed(controls.left) {
-                        transform.translation.x += -move_amount * 0.1;
+            }
+            if let Some(direction) = dash.filter(|_| player.state == PlayerState::Alive) {
+                let (state, speed, frames) = if direction == forward {
+                    (PlayerState::Dashing, stats.dash_speed, stats.dash_frames)
+                } else {
+                    (
+                        PlayerState::Backdashing,
+                        stats.backdash_speed,
+                        stats.backdash_frames,
+                    )
+                };
+                player
+                    .dash_timer
+                    .set_duration(Duration::from_secs_f32(frames_to_secs(frames)));
+                player.dash_timer.reset();
+                if state_machine.transition(entity, &mut player, state) {
+                    player.velocity = direction * speed;
+                    player.running = state == PlayerState::Dashing;
+                }
+            }
+
+            match player.state {
+                PlayerState::Alive | PlayerState::TakingDamage | PlayerState::Wiff => {
+                    if held != forward {
+                        player.running = false;
                     }
+                    let top_speed = if player.state == PlayerState::Wiff {
+                        stats.walk_speed * 0.1
+                    } else if player.running {
+                        stats.run_speed
+                    } else {
+                        stats.walk_speed
+                    };
+                    let target = held * top_speed;
+                    let speeding_up =
+                        target * player.velocity >= 0.0 && target.abs() > player.velocity.abs();
+                    let rate = if speeding_up {
+                        stats.acceleration
+                    } else {
+                        stats.deceleration
+                    };
+                    player.velocity = approach(player.velocity, target, rate * delta);
                 }
-                if matches!(player.state, PlayerState::Alive | PlayerState::TakingDamage)
-                    && keyboard_input.just_pressed(controls.attack)
+                // Dashes keep the speed they started with.
+                PlayerState::Dashing | PlayerState::Backdashing => {}
+                _ => {
+                    player.velocity = 0.0;
+                    player.running = false;
+                }
+            }
+            transform.translation.x += player.velocity * delta;
+            if matches!(player.state, PlayerState::Alive | PlayerState::TakingDamage) {
+                transform.translation.x += held * clash_speed;
+            }
+
+            if input.attack_just_pressed() {
+                buffer.attack = Some(0.0);
+            }
+            if let Some(age) = buffer.attack {
+                // A press used while taking damage is a parry, which has its own leniency.
+                let window = if player.state == PlayerState::TakingDamage {
+                    buffer_settings.parry_frames
+                } else {
+                    buffer_settings.attack_frames
+                };
+                let expired = age > frames_to_secs(window);
+                if expired
+                    || (matches!(player.state, PlayerState::Alive | PlayerState::TakingDamage)
+                        && player_attack(&mut ev_attack, &mut player, &entity))
                 {
-                    player_attack(&mut ev_attack, &mut player, &entity);
+                    buffer.attack = None;
+                } else {
+                    buffer.attack = Some(age + time.delta_seconds());
                 }
+            }
 
-                transform.translation.x = transform
-                    .translation
-                    .x
-                    .clamp(bounds.min.x + 50., bounds.max.x - 50.);
+            let half_width = player.definition().size / 2.0;
+            let clamped = transform
+                .translation
+                .x
+                .clamp(bounds.min.x + half_width, bounds.max.x - half_width);
+            if clamped != transform.translation.x {
+                player.velocity = 0.0;
             }
+            transform.translation.x = clamped;
         }
     }
 }
 
-fn player_attack(ev_attack: &mut EventWriter<AttackEvent>, player: &mut Player, entity: &Entity) {
+/// Returns whether the attack went out, so a buffered press knows it has been used.
+fn player_attack(
+    ev_attack: &mut EventWriter<AttackEvent>,
+    player: &mut Player,
+    entity: &Entity,
+) -> bool {
     println!(
         "Player {:?} attack timer: {:?}",
         player.player_number,
@@ -182,77 +503,136 @@ fn player_attack(ev_attack: &mut EventWriter<AttackEvent>, player: &mut Player,
     if player.attack_timer.finished() {
         println!("Player {:?} attacking!", player.player_number);
         player.attack_timer.reset();
-        ev_attack.send(AttackEvent(*entity));
+        ev_attack.send(AttackEvent {
+            attacker: *entity,
+            origin: None,
+            range: player.definition().combat.attack_range,
+            unparryable: false,
+        });
+        return true;
     }
+    false
 }
 
-fn check_attack_hit(
+#[allow(clippy::too_many_arguments)]
+pub fn check_attack_hit(
     mut ev_attack: EventReader<AttackEvent>,
     mut ev_clash: EventWriter<ClashEvent>,
-    mut ev_player_state_change: EventWriter<PlayerStateChangeEvent>,
+    mut ev_combo_hit: EventWriter<ComboHit>,
+    mut ev_combo_ended: EventWriter<ComboEnded>,
+    mut state_machine: PlayerStateMachine,
     mut query: Query<(&mut Player, &Transform, Entity)>,
+    clash_counter: Res<ClashCounter>,
+    clash_rules: Res<ClashRules>,
+    combo_rules: Res<ComboRules>,
+    match_settings: Res<MatchSettings>,
 ) {
     for ev in ev_attack.read() {
-        let mut attacking_player = None;
-        let mut defending_player = None;
-
-        for p in query.iter_mut() {
-            if p.2 == ev.0 {
-                attacking_player = Some(p);
-            } else {
-                defending_player = Some(p);
-            }
-        }
+        let Ok((attacker, attacker_transform, _)) = query.get(ev.attacker) else {
+            continue;
+        };
 
-        if attacking_player.is_none() || defending_player.is_none() {
+        if attacker.state == PlayerState::Dead {
             continue;
         }
 
-        let mut attacking_player = attacking_player.unwrap();
-        let mut defending_player = defending_player.unwrap();
+        let attacker_team = attacker.team;
+        let attacker_stats = &attacker.definition().combat;
+        let attacker_position = ev.origin.unwrap_or(attacker_transform.translation);
+        // Only swinging back in person counts as a parry.
+        let parry_target = match attacker.state {
+            PlayerState::TakingDamage if ev.origin.is_none() => attacker.last_hit_by,
+            _ => None,
+        };
+
+        let targets: Vec<Entity> = query
+            .iter()
+            .filter(|(player, transform, entity)| {
+                *entity != ev.attacker
+                    && player.state != PlayerState::Dead
+                    && !player.invulnerable()
+                    && (match_settings.friendly_fire || player.team != attacker_team)
+                    && transform.translation.distance(attacker_position) < ev.range
+            })
+            .map(|(.., entity)| entity)
+            .collect();
 
-        if attacking_player.0.state == PlayerState::Dead {
-            return;
+        if targets.is_empty() {
+            // Special moves miss into their own recovery. A parry that misses changes
+            // nothing: the hit being parried still lands when the window closes.
+            if let Ok((mut attacker, ..)) = query.get_mut(ev.attacker) {
+                if ev.origin.is_none() && attacker.state == PlayerState::Alive {
+                    state_machine.transition(ev.attacker, &mut attacker, PlayerState::Wiff);
+                }
+            }
+            continue;
         }
 
-        if attacking_player
-            .1
-            .translation
-            .distance(defending_player.1.translation)
-            < 100.0
-        {
-            if attacking_player.0.state == PlayerState::TakingDamage {
-                println!("Player {:?} parried!", attacking_player.0.player_number);
-                attacking_player.0.state = PlayerState::Clashing;
-                defending_player.0.state = PlayerState::Clashing;
-                ev_player_state_change.send(PlayerStateChangeEvent(
-                    attacking_player.2,
-                    PlayerState::Clashing,
-                ));
-                ev_player_state_change.send(PlayerStateChangeEvent(
-                    defending_player.2,
-                    PlayerState::Clashing,
-                ));
-                ev_clash.send(ClashEvent(attacking_player.2, defending_player.2));
-            } else {
-                match defending_player.0.state {
-                    PlayerState::Alive | PlayerState::Wiff => {
-                        defending_player.0.state = PlayerState::TakingDamage;
-                        defending_player.0.parry_timer.reset();
-                        ev_player_state_change.send(PlayerStateChangeEvent(
-                            defending_player.2,
-                            PlayerState::TakingDamage,
-                        ));
-                    }
-                    _ => {}
+        // Attacking back at whoever hit you is a parry; anyone else in range just gets hit.
+        if let Some(parry_target) = parry_target.filter(|entity| targets.contains(entity)) {
+            let Ok([(mut attacker, ..), (mut defender, ..)]) =
+                query.get_many_mut([ev.attacker, parry_target])
+            else {
+                continue;
+            };
+            // Both clash or neither does: a parry against someone who can't clash, like a
+            // fighter mid long strike, doesn't go through and the hit still lands.
+            if state_machine.can_transition(&attacker, PlayerState::Clashing)
+                && state_machine.can_transition(&defender, PlayerState::Clashing)
+            {
+                state_machine.transition(ev.attacker, &mut attacker, PlayerState::Clashing);
+                state_machine.transition(parry_target, &mut defender, PlayerState::Clashing);
+                println!("Player {:?} parried!", attacker.player_number);
+                ev_clash.send(ClashEvent(ev.attacker, parry_target));
+            }
+            continue;
+        }
+
+        let parry_window = clash_rules.parry_window.at(clash_counter.0);
+        for target in targets {
+            let Ok((mut defender, ..)) = query.get_mut(target) else {
+                continue;
+            };
+            let parryable = matches!(
+                defender.state,
+                PlayerState::Alive
+                    | PlayerState::Wiff
+                    | PlayerState::LongStrike
+                    | PlayerState::Firing
+                    | PlayerState::Dashing
+                    | PlayerState::Backdashing
+            );
+            // No parry window in hitstun or against unparryable hits: they land straight away.
+            let lands = defender.state == PlayerState::Hitstun
+                || (ev.unparryable && (parryable || defender.state == PlayerState::TakingDamage));
+            if lands {
+                let combo = defender.state == PlayerState::Hitstun;
+                defender.last_hit_by = Some(ev.attacker);
+                let damage = land_hit(
+                    target,
+                    &mut defender,
+                    attacker_stats,
+                    &combo_rules,
+                    &mut state_machine,
+                );
+                if combo {
+                    ev_combo_hit.send(ComboHit {
+                        attacker: ev.attacker,
+                        defender: target,
+                        hits: defender.combo_hits,
+                        damage,
+                    });
+                }
+                if defender.state == PlayerState::Dead {
+                    ev_combo_ended.send(combo_ended(target, &defender));
                 }
+            } else if parryable {
+                defender
+                    .parry_timer
+                    .set_duration(Duration::from_secs_f32(parry_window));
+                defender.last_hit_by = Some(ev.attacker);
+                state_machine.transition(target, &mut defender, PlayerState::TakingDamage);
             }
-        } else {
-            attacking_player.0.state = PlayerState::Wiff;
-            ev_player_state_change.send(PlayerStateChangeEvent(
-                attacking_player.2,
-                PlayerState::Wiff,
-            ));
         }
     }
 }
@@ -264,30 +644,23 @@ struct ClashPushback {
 
 fn clash_players(
     mut ev_clash: EventReader<ClashEvent>,
-    mut query: Query<(&mut Player, &mut Transform)>,
+    mut query: Query<(&mut Player, &Transform, Entity)>,
     mut commands: Commands,
+    mut state_machine: PlayerStateMachine,
     mut clash_counter: ResMut<ClashCounter>,
+    clash_rules: Res<ClashRules>,
 ) {
     for ev in ev_clash.read() {
-        let mut p1 = None;
-        let mut p2 = None;
-        for p in query.iter_mut() {
-            if p.0.player_number == 1 {
-                p1 = Some(p);
-            } else {
-                p2 = Some(p);
-            }
-        }
-
-        if p1.is_none() || p2.is_none() {
+        let Ok([(mut p1, t1, e1), (mut p2, t2, e2)]) = query.get_many_mut([ev.0, ev.1]) else {
+            warn!(
+                "Clash between {:?} and {:?} without both fighters",
+                ev.0, ev.1
+            );
             continue;
-        }
-
-        let (mut p1, t1) = p1.unwrap();
-        let (mut p2, t2) = p2.unwrap();
+        };
 
-        p1.state = PlayerState::Clashing;
-        p2.state = PlayerState::Clashing;
+        state_machine.transition(e1, &mut p1, PlayerState::Clashing);
+        state_machine.transition(e2, &mut p2, PlayerState::Clashing);
 
         let p1_offset = if t1.translation.x < t2.translation.x {
             -1
@@ -298,14 +671,20 @@ fn clash_players(
 
         // commands.entity(ev.0).insert(ClashPushback{offset: p1_offset, timer: Timer::from_seconds(0.8, TimerMode::Once)});
         // commands.entity(ev.1).insert(ClashPushback{offset: p2_offset, timer: Timer::from_seconds(0.8, TimerMode::Once)});
+        // Each fighter is pushed as far as the other one shoves.
+        let pushback = clash_rules.pushback_distance.at(clash_counter.0);
+        let p1_pushback = pushback * p2.definition().combat.pushback_scale;
+        let p2_pushback = pushback * p1.definition().combat.pushback_scale;
+        let pushback_duration =
+            Duration::from_secs_f32(clash_rules.pushback_duration.at(clash_counter.0));
         clash_counter.0 += 1;
         commands.entity(ev.0).insert(Animator::new(Tween::new(
             EaseFunction::QuadraticOut,
-            Duration::from_secs_f32(0.2),
+            pushback_duration,
             TransformPositionLens {
                 start: t1.translation,
                 end: Vec3::new(
-                    t1.translation.x + (p1_offset as f32 * 300.0),
+                    t1.translation.x + (p1_offset as f32 * p1_pushback),
                     t1.translation.y,
                     t1.translation.z,
                 ),
@@ -314,11 +693,11 @@ fn clash_players(
 
         commands.entity(ev.1).insert(Animator::new(Tween::new(
             EaseFunction::QuadraticOut,
-            Duration::from_secs_f32(0.2),
+            pushback_duration,
             TransformPositionLens {
                 start: t2.translation,
                 end: Vec3::new(
-                    t2.translation.x + (p2_offset as f32 * 300.0),
+                    t2.translation.x + (p2_offset as f32 * p2_pushback),
                     t2.translation.y,
                     t2.translation.z,
                 ),
@@ -328,94 +707,276 @@ fn clash_players(
 }
 
 fn push_back_player_with_clash(
-    mut query: Query<(&mut Player, &mut ClashPushback)>,
+    mut query: Query<(&mut Player, &mut ClashPushback, Entity)>,
+    mut state_machine: PlayerStateMachine,
     time: Res<Time>,
 ) {
-    for (mut player, mut clash_pushback) in query.iter_mut() {
+    for (mut player, mut clash_pushback, entity) in query.iter_mut() {
         clash_pushback.timer.tick(time.delta());
         if clash_pushback.timer.finished() {
-            player.state = PlayerState::Alive;
+            state_machine.transition(entity, &mut player, PlayerState::Alive);
         }
     }
 }
 
+/// One player the lobby sent into the match.
+#[derive(Debug, Clone, Reflect)]
+pub struct LineupEntry {
+    pub player_number: u8,
+    /// `None` for fighters driven from elsewhere, like a spectated match.
+    pub controls: Option<ControlDevice>,
+    /// Index into [`FIGHTERS`].
+    pub fighter: usize,
+    pub color: Color,
+    /// Slot in the chosen [`crate::fighter::PaletteSet`]; `None` keeps `color` whatever
+    /// set is picked, like a recorded match's colors.
+    pub palette: Option<usize>,
+    /// Name of the profile results are recorded to; `None` for guests.
+    pub profile: Option<String>,
+}
+
+/// Everyone who is in the next match, filled in by the lobby.
+#[derive(Resource, Debug, Default, Reflect)]
+#[reflect(Resource)]
+pub struct Lineup(pub Vec<LineupEntry>);
+
+#[allow(clippy::too_many_arguments)]
 fn spawn_players(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     mut controls: ResMut<Controls>,
+    mut slots: ResMut<PlayerSlots>,
+    lineup: Res<Lineup>,
+    match_settings: Res<MatchSettings>,
+    starting_distance: Res<StartingDistance>,
+    combo_rules: Res<ComboRules>,
 ) {
-    // Player 1
-    let p_1_color = materials.add(Color::rgb(1.0, 0.7, 0.6));
-    let mut binding = commands.spawn(MaterialMesh2dBundle {
-        mesh: Mesh2dHandle(meshes.add(Rectangle::new(100.0, 100.0))),
-        material: p_1_color.clone(),
-        transform: Transform::from_xyz(-300.0, 0.0, 0.0),
-        ..default()
-    });
-    let p1_e = binding.insert(Player {
-        player_number: 1,
-        color_mesh_handle: p_1_color,
-        color: Color::rgb(1.0, 0.7, 0.6),
-        ..default()
-    });
-
-    // player 1 default controls
-    controls.control_map.insert(
-        p1_e.id(),
-        crate::ControlPerPlayer {
-            left: KeyCode::KeyA,
-            right: KeyCode::KeyD,
-            attack: KeyCode::KeyS,
-        },
-    );
+    let mut entries: Vec<_> = lineup
+        .0
+        .iter()
+        .map(|entry| (match_settings.team_for(entry.player_number), entry))
+        .collect();
+    entries.sort_by_key(|(team, entry)| (*team, entry.player_number));
 
-    // Player 2
-    let p_2_color = materials.add(Color::rgb(0.8, 1.0, 0.6));
-    let mut binding = commands.spawn(MaterialMesh2dBundle {
-        mesh: Mesh2dHandle(meshes.add(Rectangle::new(100.0, 100.0))),
-        material: p_2_color.clone(),
-        transform: Transform::from_xyz(300.0, 0.0, 0.0),
-        ..default()
-    });
-    let p2_e = binding.insert(Player {
-        player_number: 2,
-        color_mesh_handle: p_2_color,
-        color: Color::rgb(0.8, 1.0, 0.6),
-        ..default()
-    });
-
-    // player 2 default controls
-    controls.control_map.insert(
-        p2_e.id(),
-        crate::ControlPerPlayer {
-            left: KeyCode::ArrowLeft,
-            right: KeyCode::ArrowRight,
-            attack: KeyCode::ArrowDown,
-        },
-    );
+    let count = entries.len();
+    for (index, (team, entry)) in entries.into_iter().enumerate() {
+        let fighter = &FIGHTERS[entry.fighter % FIGHTERS.len()];
+        let material = materials.add(entry.color);
+        let entity = commands
+            .spawn(MaterialMesh2dBundle {
+                mesh: Mesh2dHandle(meshes.add(Rectangle::new(fighter.size, fighter.size))),
+                material: material.clone(),
+                transform: Transform::from_xyz(
+                    starting_x(index, count, starting_distance.0),
+                    0.0,
+                    0.0,
+                ),
+                ..default()
+            })
+            .insert((
+                Player {
+                    player_number: entry.player_number,
+                    fighter: entry.fighter % FIGHTERS.len(),
+                    team,
+                    color_mesh_handle: material,
+                    color: entry.color,
+                    palette: entry.palette,
+                    health: combo_rules.max_health,
+                    attack_timer: Timer::from_seconds(
+                        frames_to_secs(fighter.combat.attack_cooldown_frames),
+                        TimerMode::Once,
+                    ),
+                    ..default()
+                },
+                PlayerInput::default(),
+                InputBuffer::default(),
+            ))
+            .with_children(|parent| {
+                spawn_state_cues(
+                    parent,
+                    &mut meshes,
+                    &mut materials,
+                    fighter.size,
+                    entry.player_number,
+                );
+            })
+            .id();
+
+        if let Some(profile) = &entry.profile {
+            commands
+                .entity(entity)
+                .insert(PlayerProfile(profile.clone()));
+        }
+        if let Some(device) = entry.controls {
+            controls.control_map.insert(entity, device);
+        }
+        slots.insert(entry.player_number, entity);
+    }
 }
 
-fn player_timer_update(
+fn despawn_players(
+    mut commands: Commands,
+    mut controls: ResMut<Controls>,
+    mut slots: ResMut<PlayerSlots>,
+    players: Query<Entity, With<Player>>,
+) {
+    for entity in players.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    controls.control_map.clear();
+    slots.clear();
+}
+
+/// Frees the slot of any fighter that was despawned without going through the lineup.
+fn release_despawned_slots(
+    mut removed: RemovedComponents<Player>,
+    mut slots: ResMut<PlayerSlots>,
+    mut controls: ResMut<Controls>,
+) {
+    for entity in removed.read() {
+        slots.0.retain(|_, slot_entity| *slot_entity != entity);
+        controls.control_map.remove(&entity);
+    }
+}
+
+/// Deals the next hit of a combo to `player`, who is knocked out or left in hitstun.
+/// `attacker` scales the damage and hitstun. Returns the damage dealt.
+fn land_hit(
+    entity: Entity,
+    player: &mut Player,
+    attacker: &CombatStats,
+    combo_rules: &ComboRules,
+    state_machine: &mut PlayerStateMachine,
+) -> f32 {
+    let hit = player.combo_hits + 1;
+    deal_damage(
+        entity,
+        player,
+        combo_rules.damage(hit) * attacker.damage_scale,
+        combo_rules.hitstun(hit).mul_f32(attacker.hitstun_scale),
+        state_machine,
+    )
+}
+
+/// Counts a hit of `damage` towards the combo on `player`, who is knocked out or left
+/// in hitstun for `hitstun`. Returns the damage dealt, which never goes past the
+/// health left.
+pub fn deal_damage(
+    entity: Entity,
+    player: &mut Player,
+    damage: f32,
+    hitstun: Duration,
+    state_machine: &mut PlayerStateMachine,
+) -> f32 {
+    player.combo_hits += 1;
+    let damage = damage.min(player.health);
+    player.health -= damage;
+    player.combo_damage += damage;
+    if player.health <= 0.0 {
+        state_machine.transition(entity, player, PlayerState::Dead);
+    } else {
+        player.hitstun_timer.set_duration(hitstun);
+        player.hitstun_timer.reset();
+        state_machine.transition(entity, player, PlayerState::Hitstun);
+    }
+    damage
+}
+
+pub fn combo_ended(entity: Entity, player: &Player) -> ComboEnded {
+    ComboEnded {
+        attacker: player.last_hit_by,
+        defender: entity,
+        hits: player.combo_hits,
+        damage: player.combo_damage,
+        knocked_out: player.state == PlayerState::Dead,
+    }
+}
+
+pub fn player_timer_update(
     time: Res<Time>,
     mut query: Query<(&mut Player, Entity)>,
-    mut ev_player_state_change: EventWriter<PlayerStateChangeEvent>,
+    mut state_machine: PlayerStateMachine,
+    combo_rules: Res<ComboRules>,
+    mut ev_combo_ended: EventWriter<ComboEnded>,
 ) {
+    let combat_stats: HashMap<Entity, &CombatStats> = query
+        .iter()
+        .map(|(player, entity)| (entity, &player.definition().combat))
+        .collect();
     for (mut player, entity) in query.iter_mut() {
         player.parry_timer.tick(time.delta());
         player.attack_timer.tick(time.delta());
         player.clashing_timer.tick(time.delta());
+        player.hitstun_timer.tick(time.delta());
+        player.special_timer.tick(time.delta());
+        player.throw_timer.tick(time.delta());
+        player.dash_timer.tick(time.delta());
+        if player.hitstun_timer.finished() && player.state == PlayerState::Hitstun {
+            // Dropped: the fighter recovered before the next hit.
+            let ended = combo_ended(entity, &player);
+            if state_machine.transition(entity, &mut player, PlayerState::Alive) {
+                ev_combo_ended.send(ended);
+            }
+        }
+        if player.special_timer.finished()
+            && matches!(
+                player.state,
+                PlayerState::Reversal | PlayerState::LongStrike | PlayerState::Firing
+            )
+        {
+            state_machine.transition(entity, &mut player, PlayerState::Alive);
+        }
+        if player.throw_timer.finished()
+            && matches!(player.state, PlayerState::Throwing | PlayerState::Teching)
+        {
+            state_machine.transition(entity, &mut player, PlayerState::Alive);
+        }
+        if player.dash_timer.finished()
+            && matches!(
+                player.state,
+                PlayerState::Dashing | PlayerState::Backdashing
+            )
+        {
+            state_machine.transition(entity, &mut player, PlayerState::Alive);
+        }
         if player.parry_timer.just_finished() && player.state == PlayerState::TakingDamage {
-            player.state = PlayerState::Dead;
-            ev_player_state_change.send(PlayerStateChangeEvent(entity, PlayerState::Dead));
+            let attacker = player
+                .last_hit_by
+                .and_then(|attacker| combat_stats.get(&attacker).copied())
+                .unwrap_or(&CombatStats::UNSCALED);
+            land_hit(
+                entity,
+                &mut player,
+                attacker,
+                &combo_rules,
+                &mut state_machine,
+            );
+            if player.state == PlayerState::Dead {
+                ev_combo_ended.send(combo_ended(entity, &player));
+            }
         }
         if player.attack_timer.finished() && player.state == PlayerState::Wiff {
-            player.state = PlayerState::Alive;
-            ev_player_state_change.send(PlayerStateChangeEvent(entity, PlayerState::Alive));
+            state_machine.transition(entity, &mut player, PlayerState::Alive);
         }
         if player.clashing_timer.finished() && player.state == PlayerState::Clashing {
-            player.state = PlayerState::Alive;
-            ev_player_state_change.send(PlayerStateChangeEvent(entity, PlayerState::Alive));
+            state_machine.transition(entity, &mut player, PlayerState::Alive);
+        }
+    }
+}
+
+/// Follows the palette set picked in the options, even mid-match.
+fn recolor_players(save: Res<SaveData>, mut players: Query<&mut Player>) {
+    if !save.is_changed() {
+        return;
+    }
+    let colors = save.video.palettes.colors();
+    for mut player in players.iter_mut() {
+        if let Some(palette) = player.palette {
+            let color = colors[palette % colors.len()];
+            if player.color != color {
+                player.color = color;
+            }
         }
     }
 }
@@ -449,7 +1010,83 @@ fn update_player_color(
                 }
                 PlayerState::Wiff => {}
                 PlayerState::TakingDamage => {}
+                PlayerState::Hitstun => {}
+                PlayerState::Reversal | PlayerState::LongStrike | PlayerState::Firing => {}
+                PlayerState::Throwing | PlayerState::Thrown | PlayerState::Teching => {}
+                PlayerState::Dashing | PlayerState::Backdashing => {}
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{attack, hit_app, player, spawn_fighter, step};
+
+    #[test]
+    fn hit_by_long_strike_then_attacking_out_of_range_still_takes_damage() {
+        let mut app = hit_app();
+        let striker = spawn_fighter(&mut app, 1, 0.0);
+        let defender = spawn_fighter(&mut app, 2, 300.0);
+
+        attack(&mut app, striker, 350.0);
+        step(&mut app, 1);
+        assert_eq!(player(&app, defender).state, PlayerState::TakingDamage);
+
+        let reach = player(&app, defender).definition().combat.attack_range;
+        attack(&mut app, defender, reach);
+        step(&mut app, 1);
+        assert_eq!(player(&app, defender).state, PlayerState::TakingDamage);
+
+        step(&mut app, 10);
+        let defender = player(&app, defender);
+        assert_eq!(defender.state, PlayerState::Hitstun);
+        assert!(defender.health < 100.0);
+    }
+
+    #[test]
+    fn attacking_back_in_range_parries() {
+        let mut app = hit_app();
+        let attacker = spawn_fighter(&mut app, 1, 0.0);
+        let defender = spawn_fighter(&mut app, 2, 80.0);
+
+        attack(&mut app, attacker, 100.0);
+        step(&mut app, 1);
+        attack(&mut app, defender, 100.0);
+        step(&mut app, 1);
+
+        assert_eq!(player(&app, attacker).state, PlayerState::Clashing);
+        assert_eq!(player(&app, defender).state, PlayerState::Clashing);
+        assert_eq!(player(&app, defender).health, 100.0);
+    }
+
+    #[test]
+    fn parrying_a_fighter_who_cannot_clash_changes_nobody() {
+        let mut app = hit_app();
+        let striker = app
+            .world
+            .spawn((
+                Player {
+                    team: 1,
+                    state: PlayerState::LongStrike,
+                    special_timer: Timer::from_seconds(1.0, TimerMode::Once),
+                    ..default()
+                },
+                Transform::from_xyz(0.0, 0.0, 0.0),
+            ))
+            .id();
+        let defender = spawn_fighter(&mut app, 2, 80.0);
+
+        attack(&mut app, striker, 350.0);
+        step(&mut app, 1);
+        attack(&mut app, defender, 100.0);
+        step(&mut app, 1);
+        assert_eq!(player(&app, striker).state, PlayerState::LongStrike);
+        assert_eq!(player(&app, defender).state, PlayerState::TakingDamage);
+
+        step(&mut app, 10);
+        assert_eq!(player(&app, defender).state, PlayerState::Hitstun);
+        assert!(player(&app, defender).health < 100.0);
+    }
+}
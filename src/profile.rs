@@ -0,0 +1,428 @@
+use bevy::{prelude::*, utils::HashMap, window::ReceivedCharacter};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    player::{PlayerState, PlayerStateChangeEvent, ResetPlayers},
+    save::SaveData,
+    GameState, RoundOver,
+};
+
+pub struct ProfilePlugin;
+
+impl Plugin for ProfilePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ProfileEditor::default())
+            .insert_resource(StatsScreen::default())
+            .add_systems(Startup, spawn_profile_overlay)
+            .add_systems(OnExit(GameState::Lobby), close_profile_overlay)
+            .add_systems(
+                Update,
+                (
+                    (edit_profile_name, cycle_stats_screen).run_if(in_state(GameState::Lobby)),
+                    update_profile_overlay,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Update,
+                track_profile_stats.run_if(in_state(GameState::Match)),
+            );
+    }
+}
+
+const MAX_NAME_LEN: usize = 12;
+
+#[derive(Debug, Default, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeadToHead {
+    pub opponent: String,
+    pub wins: u32,
+    pub losses: u32,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfileStats {
+    /// Rounds won; a round is won by being on the last team standing.
+    pub wins: u32,
+    pub losses: u32,
+    /// Every round played, including ones nobody won.
+    pub rounds: u32,
+    /// Times this fighter was hit and had a parry window to answer in.
+    pub parry_chances: u32,
+    pub parries: u32,
+    /// Most clashes this fighter was in during a single round.
+    pub longest_clash_streak: u32,
+    pub head_to_head: Vec<HeadToHead>,
+}
+
+impl ProfileStats {
+    pub fn parry_rate(&self) -> Option<f32> {
+        (self.parry_chances > 0).then(|| self.parries as f32 / self.parry_chances as f32)
+    }
+
+    fn head_to_head_mut(&mut self, opponent: &str) -> &mut HeadToHead {
+        let index = match self
+            .head_to_head
+            .iter()
+            .position(|record| record.opponent == opponent)
+        {
+            Some(index) => index,
+            None => {
+                self.head_to_head.push(HeadToHead {
+                    opponent: opponent.to_string(),
+                    ..default()
+                });
+                self.head_to_head.len() - 1
+            }
+        };
+        &mut self.head_to_head[index]
+    }
+}
+
+/// A named local player, picked in the lobby so results follow them between matches.
+#[derive(Debug, Default, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub name: String,
+    pub stats: ProfileStats,
+    /// The fighter last picked in the lobby, which tournament matches are played with.
+    pub fighter: usize,
+}
+
+/// Which profile a fighter is playing as. Guests don't have one.
+#[derive(Debug, Clone, Component)]
+pub struct PlayerProfile(pub String);
+
+/// The name being typed for a new profile. While it is open the lobby ignores input.
+#[derive(Debug, Default, Resource)]
+pub struct ProfileEditor {
+    pub name: Option<String>,
+}
+
+pub fn not_editing(editor: Res<ProfileEditor>) -> bool {
+    editor.name.is_none()
+}
+
+/// Which profile's stats are being shown, if any.
+#[derive(Debug, Default, Resource)]
+struct StatsScreen(Option<usize>);
+
+#[derive(Component)]
+struct ProfileOverlay;
+
+#[derive(Component)]
+struct ProfileOverlayText;
+
+fn edit_profile_name(
+    mut keyboard_input: ResMut<ButtonInput<KeyCode>>,
+    mut ev_character: EventReader<ReceivedCharacter>,
+    mut editor: ResMut<ProfileEditor>,
+    mut save: ResMut<SaveData>,
+) {
+    let Some(name) = editor.name.as_mut() else {
+        ev_character.clear();
+        if keyboard_input.just_pressed(KeyCode::F8) {
+            editor.name = Some(String::new());
+        }
+        return;
+    };
+
+    for ev in ev_character.read() {
+        for c in ev.char.chars() {
+            if (c.is_alphanumeric() || c == ' ' || c == '-' || c == '_')
+                && name.chars().count() < MAX_NAME_LEN
+            {
+                name.push(c);
+            }
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Backspace) {
+        name.pop();
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Enter) {
+        let trimmed = name.trim().to_string();
+        if trimmed.is_empty() {
+            return;
+        }
+        if save.profiles.iter().any(|profile| profile.name == trimmed) {
+            println!("There is already a profile called {}", trimmed);
+            return;
+        }
+        println!("Created profile {}", trimmed);
+        save.profiles.push(Profile {
+            name: trimmed,
+            ..default()
+        });
+        editor.name = None;
+    } else if keyboard_input.just_pressed(KeyCode::Escape) {
+        editor.name = None;
+    }
+
+    // The lobby runs after this and must not see the keys used for typing.
+    for key in [KeyCode::Enter, KeyCode::Backspace, KeyCode::Escape] {
+        keyboard_input.clear_just_pressed(key);
+    }
+}
+
+fn cycle_stats_screen(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    editor: Res<ProfileEditor>,
+    save: Res<SaveData>,
+    mut screen: ResMut<StatsScreen>,
+) {
+    if editor.name.is_some() || !keyboard_input.just_pressed(KeyCode::F9) {
+        return;
+    }
+    screen.0 = match screen.0 {
+        None if !save.profiles.is_empty() => Some(0),
+        Some(index) if index + 1 < save.profiles.len() => Some(index + 1),
+        _ => None,
+    };
+}
+
+fn close_profile_overlay(mut editor: ResMut<ProfileEditor>, mut screen: ResMut<StatsScreen>) {
+    editor.name = None;
+    screen.0 = None;
+}
+
+fn spawn_profile_overlay(mut commands: Commands) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.85).into(),
+            visibility: Visibility::Hidden,
+            z_index: ZIndex::Global(20),
+            ..default()
+        })
+        .insert(ProfileOverlay)
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle {
+                    text: Text {
+                        sections: vec![TextSection {
+                            value: String::new(),
+                            style: TextStyle {
+                                font_size: 36.0,
+                                color: Color::WHITE,
+                                font: Default::default(),
+                            },
+                        }],
+                        justify: JustifyText::Left,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(ProfileOverlayText);
+        });
+}
+
+fn stats_text(profile: &Profile) -> String {
+    let stats = &profile.stats;
+    let parry_rate = match stats.parry_rate() {
+        Some(rate) => format!("{:.0}%", rate * 100.0),
+        None => "-".to_string(),
+    };
+    let mut text = format!(
+        "{}\n\nRounds: {}\nWins: {}  Losses: {}\nParries: {} of {} ({})\nLongest clash streak: {}\n",
+        profile.name,
+        stats.rounds,
+        stats.wins,
+        stats.losses,
+        stats.parries,
+        stats.parry_chances,
+        parry_rate,
+        stats.longest_clash_streak,
+    );
+    if !stats.head_to_head.is_empty() {
+        text.push_str("\nHead to head:\n");
+        for record in stats.head_to_head.iter() {
+            text.push_str(&format!(
+                "  vs {}: {} - {}\n",
+                record.opponent, record.wins, record.losses
+            ));
+        }
+    }
+    text.push_str("\nF9 next profile");
+    text
+}
+
+fn update_profile_overlay(
+    editor: Res<ProfileEditor>,
+    screen: Res<StatsScreen>,
+    save: Res<SaveData>,
+    mut overlay: Query<&mut Visibility, With<ProfileOverlay>>,
+    mut text: Query<&mut Text, With<ProfileOverlayText>>,
+) {
+    let (Ok(mut visibility), Ok(mut text)) = (overlay.get_single_mut(), text.get_single_mut())
+    else {
+        return;
+    };
+
+    let value = if let Some(name) = &editor.name {
+        Some(format!(
+            "New profile name:\n\n{}_\n\nEnter to save, Esc to cancel",
+            name
+        ))
+    } else {
+        screen
+            .0
+            .and_then(|index| save.profiles.get(index))
+            .map(stats_text)
+    };
+
+    match value {
+        Some(value) => {
+            *visibility = Visibility::Inherited;
+            if text.sections[0].value != value {
+                text.sections[0].value = value;
+            }
+        }
+        None => *visibility = Visibility::Hidden,
+    }
+}
+
+#[derive(Default)]
+struct StatTracker {
+    states: HashMap<Entity, PlayerState>,
+    streaks: HashMap<Entity, u32>,
+}
+
+fn profile_stats<'a>(save: &'a mut SaveData, name: &str) -> Option<&'a mut ProfileStats> {
+    save.profiles
+        .iter_mut()
+        .find(|profile| profile.name == name)
+        .map(|profile| &mut profile.stats)
+}
+
+fn track_profile_stats(
+    mut ev_player_state_change: EventReader<PlayerStateChangeEvent>,
+    mut ev_round_over: EventReader<RoundOver>,
+    mut ev_reset: EventReader<ResetPlayers>,
+    profiles: Query<&PlayerProfile>,
+    mut save: ResMut<SaveData>,
+    mut tracker: Local<StatTracker>,
+) {
+    if ev_reset.read().count() > 0 {
+        tracker.streaks.clear();
+    }
+
+    // Most state changes don't touch the stats, and marking the save changed writes it
+    // to disk, so it is only marked when something was counted.
+    let mut changed = false;
+    let save_data = save.bypass_change_detection();
+
+    for event in ev_player_state_change.read() {
+        let (entity, to) = (event.0, event.1);
+        let from = tracker.states.insert(entity, to).unwrap_or_default();
+        let Ok(PlayerProfile(name)) = profiles.get(entity) else {
+            continue;
+        };
+
+        let streak = tracker.streaks.entry(entity).or_default();
+        if to == PlayerState::Clashing {
+            *streak += 1;
+        }
+        let streak = *streak;
+
+        let Some(stats) = profile_stats(save_data, name) else {
+            continue;
+        };
+        match (from, to) {
+            (_, PlayerState::TakingDamage) => stats.parry_chances += 1,
+            (PlayerState::TakingDamage, PlayerState::Clashing) => stats.parries += 1,
+            _ if streak <= stats.longest_clash_streak => continue,
+            _ => {}
+        }
+        stats.longest_clash_streak = stats.longest_clash_streak.max(streak);
+        changed = true;
+    }
+
+    for round in ev_round_over.read() {
+        let name_of = |entity: &Entity| profiles.get(*entity).ok().map(|profile| &profile.0);
+        for entity in round.winners.iter().chain(round.losers.iter()) {
+            if let Some(stats) = name_of(entity).and_then(|name| profile_stats(save_data, name)) {
+                stats.rounds += 1;
+                changed = true;
+            }
+        }
+
+        for winner in round.winners.iter().filter_map(name_of) {
+            for loser in round.losers.iter().filter_map(name_of) {
+                if winner == loser {
+                    continue;
+                }
+                if let Some(stats) = profile_stats(save_data, winner) {
+                    stats.head_to_head_mut(loser).wins += 1;
+                }
+                if let Some(stats) = profile_stats(save_data, loser) {
+                    stats.head_to_head_mut(winner).losses += 1;
+                }
+            }
+        }
+        for winner in round.winners.iter().filter_map(name_of) {
+            if let Some(stats) = profile_stats(save_data, winner) {
+                stats.wins += 1;
+            }
+        }
+        for loser in round.losers.iter().filter_map(name_of) {
+            if let Some(stats) = profile_stats(save_data, loser) {
+                stats.losses += 1;
+            }
+        }
+    }
+
+    if changed {
+        save.set_changed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct SaveChanged(bool);
+
+    fn note_save_changed(save: Res<SaveData>, mut changed: ResMut<SaveChanged>) {
+        changed.0 = save.is_changed();
+    }
+
+    #[test]
+    fn only_counted_state_changes_touch_the_save() {
+        let mut app = App::new();
+        let mut save = SaveData::default();
+        save.profiles.push(Profile {
+            name: "Ada".to_string(),
+            ..default()
+        });
+        app.add_event::<PlayerStateChangeEvent>()
+            .add_event::<RoundOver>()
+            .add_event::<ResetPlayers>()
+            .insert_resource(save)
+            .insert_resource(SaveChanged::default())
+            .add_systems(Update, (track_profile_stats, note_save_changed).chain());
+        let fighter = app.world.spawn(PlayerProfile("Ada".to_string())).id();
+        app.update();
+
+        app.world
+            .send_event(PlayerStateChangeEvent(fighter, PlayerState::Wiff));
+        app.update();
+        assert!(!app.world.resource::<SaveChanged>().0);
+
+        app.world
+            .send_event(PlayerStateChangeEvent(fighter, PlayerState::TakingDamage));
+        app.update();
+        assert!(app.world.resource::<SaveChanged>().0);
+        let stats = &app.world.resource::<SaveData>().profiles[0].stats;
+        assert_eq!(stats.parry_chances, 1);
+    }
+}
@@ -0,0 +1,378 @@
+use bevy::{
+    prelude::*,
+    sprite::{MaterialMesh2dBundle, Mesh2dHandle},
+};
+
+use crate::{
+    pause::not_paused,
+    player::{check_attack_hit, AttackEvent, Player, PlayerState, ResetPlayers},
+    state_machine::PlayerStateMachine,
+    GameState, MatchSettings,
+};
+
+/// Projectiles fly in a straight line until they hit a fighter, run into an opposing
+/// projectile or run out of time. Being hit by one goes through the usual parry window,
+/// and the projectile stays on the fighter until it's over: attacking back in time is a
+/// parry that sends it back at whoever threw it. Attacking with an opposing projectile
+/// in reach sends it back the other way too.
+pub struct ProjectilePlugin;
+
+impl Plugin for ProjectilePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SpawnProjectile>()
+            .register_type::<ProjectileRules>()
+            .insert_resource(ProjectileRules::default())
+            .add_systems(OnExit(GameState::Match), despawn_projectiles)
+            .add_systems(
+                Update,
+                (
+                    (
+                        spawn_projectiles,
+                        reflect_projectiles,
+                        move_projectiles,
+                        cancel_projectiles,
+                        hit_with_projectiles,
+                    )
+                        .chain()
+                        .before(check_attack_hit),
+                    (release_projectiles, clear_projectiles_on_reset)
+                        .chain()
+                        .after(check_attack_hit),
+                )
+                    .run_if(in_state(GameState::Match))
+                    .run_if(not_paused),
+            );
+    }
+}
+
+#[derive(Event)]
+pub struct SpawnProjectile {
+    pub owner: Entity,
+    /// 1.0 to fly right, -1.0 to fly left.
+    pub direction: f32,
+}
+
+#[derive(Debug, Resource, Clone, Reflect)]
+#[reflect(Resource)]
+pub struct ProjectileRules {
+    /// Units per second.
+    pub speed: f32,
+    pub lifetime_secs: f32,
+    /// Width of the hitbox, which is also how big it is drawn.
+    pub size: f32,
+    /// Speed multiplier every time a projectile is reflected.
+    pub reflect_speedup: f32,
+}
+
+impl Default for ProjectileRules {
+    fn default() -> Self {
+        ProjectileRules {
+            speed: 900.0,
+            lifetime_secs: 1.5,
+            size: 40.0,
+            reflect_speedup: 1.25,
+        }
+    }
+}
+
+#[derive(Debug, Component)]
+pub struct Projectile {
+    /// Gets the credit for a hit. Changes hands when the projectile is reflected.
+    pub owner: Entity,
+    pub team: u8,
+    /// Units per second along x.
+    pub velocity: f32,
+    pub lifetime: Timer,
+    /// The fighter it has hit, while they can still parry it. It doesn't move until
+    /// the hit lands or is parried.
+    pub hit: Option<Entity>,
+}
+
+fn can_hit(projectile: &Projectile, team: u8, match_settings: &MatchSettings) -> bool {
+    match_settings.friendly_fire || team != projectile.team
+}
+
+fn spawn_projectiles(
+    mut commands: Commands,
+    mut ev_spawn: EventReader<SpawnProjectile>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    players: Query<(&Player, &Transform)>,
+    rules: Res<ProjectileRules>,
+) {
+    for ev in ev_spawn.read() {
+        let Ok((player, transform)) = players.get(ev.owner) else {
+            continue;
+        };
+        let half_width = player.definition().size / 2.0;
+        let start =
+            transform.translation + Vec3::new(ev.direction * (half_width + rules.size), 0.0, 0.5);
+        commands
+            .spawn(MaterialMesh2dBundle {
+                mesh: Mesh2dHandle(meshes.add(Circle::new(rules.size / 2.0))),
+                material: materials.add(player.color),
+                transform: Transform::from_translation(start),
+                ..default()
+            })
+            .insert(Projectile {
+                owner: ev.owner,
+                team: player.team,
+                velocity: ev.direction * rules.speed,
+                lifetime: Timer::from_seconds(rules.lifetime_secs, TimerMode::Once),
+                hit: None,
+            });
+    }
+}
+
+fn reflect(projectile: &mut Projectile, owner: Entity, team: u8, direction: f32, speedup: f32) {
+    projectile.owner = owner;
+    projectile.team = team;
+    projectile.velocity = direction * projectile.velocity.abs() * speedup;
+    projectile.lifetime.reset();
+    projectile.hit = None;
+}
+
+/// Any attack, special moves included, sends back opposing projectiles within its reach.
+/// Attacking back at one that has just hit you is a parry, wherever its thrower is.
+pub fn reflect_projectiles(
+    mut ev_attack: EventReader<AttackEvent>,
+    mut players: Query<(&mut Player, &Transform)>,
+    mut projectiles: Query<(&mut Projectile, &Transform)>,
+    mut state_machine: PlayerStateMachine,
+    match_settings: Res<MatchSettings>,
+    rules: Res<ProjectileRules>,
+) {
+    for ev in ev_attack.read() {
+        // Projectile hits are attacks too, but they don't swing at anything.
+        if ev.origin.is_some() {
+            continue;
+        }
+        let Ok((mut player, transform)) = players.get_mut(ev.attacker) else {
+            continue;
+        };
+        for (mut projectile, projectile_transform) in projectiles.iter_mut() {
+            if projectile.hit == Some(ev.attacker) {
+                // Only while the parry window is open; the guard checks that.
+                if player.state == PlayerState::TakingDamage
+                    && state_machine.transition(ev.attacker, &mut player, PlayerState::Clashing)
+                {
+                    println!("Player {:?} parried a projectile!", player.player_number);
+                    let direction = -projectile.velocity.signum();
+                    reflect(
+                        &mut projectile,
+                        ev.attacker,
+                        player.team,
+                        direction,
+                        rules.reflect_speedup,
+                    );
+                }
+                continue;
+            }
+            let offset = projectile_transform.translation.x - transform.translation.x;
+            if projectile.hit.is_some()
+                || projectile.owner == ev.attacker
+                || !can_hit(&projectile, player.team, &match_settings)
+                || offset.abs() > ev.range + rules.size / 2.0
+            {
+                continue;
+            }
+            println!("Player {:?} reflected a projectile!", player.player_number);
+            reflect(
+                &mut projectile,
+                ev.attacker,
+                player.team,
+                offset.signum(),
+                rules.reflect_speedup,
+            );
+        }
+    }
+}
+
+pub fn move_projectiles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut projectiles: Query<(Entity, &mut Projectile, &mut Transform)>,
+) {
+    for (entity, mut projectile, mut transform) in projectiles.iter_mut() {
+        if projectile.hit.is_some() {
+            continue;
+        }
+        transform.translation.x += projectile.velocity * time.delta_seconds();
+        projectile.lifetime.tick(time.delta());
+        if projectile.lifetime.finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Projectiles from opposing sides that meet destroy each other.
+pub fn cancel_projectiles(
+    mut commands: Commands,
+    projectiles: Query<(Entity, &Projectile, &Transform)>,
+    match_settings: Res<MatchSettings>,
+    rules: Res<ProjectileRules>,
+) {
+    let all: Vec<_> = projectiles
+        .iter()
+        .filter(|(_, projectile, _)| projectile.hit.is_none())
+        .collect();
+    let mut cancelled = Vec::new();
+    for (index, (a, projectile_a, transform_a)) in all.iter().enumerate() {
+        for (b, projectile_b, transform_b) in all.iter().skip(index + 1) {
+            if cancelled.contains(a) || cancelled.contains(b) {
+                continue;
+            }
+            let opposed = projectile_a.owner != projectile_b.owner
+                && can_hit(projectile_a, projectile_b.team, &match_settings);
+            let touching =
+                (transform_a.translation.x - transform_b.translation.x).abs() < rules.size;
+            if opposed && touching {
+                cancelled.extend([*a, *b]);
+            }
+        }
+    }
+    for entity in cancelled {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// A projectile that reaches a fighter it can hit attacks from where it is, then waits
+/// on them to see if the hit is parried.
+pub fn hit_with_projectiles(
+    mut ev_attack: EventWriter<AttackEvent>,
+    players: Query<(Entity, &Player, &Transform)>,
+    mut projectiles: Query<(&mut Projectile, &Transform)>,
+    match_settings: Res<MatchSettings>,
+    rules: Res<ProjectileRules>,
+) {
+    // The hitbox reaches past the projectile's own by half of whoever it hits.
+    let reach = |player: &Player| player.definition().size / 2.0 + rules.size / 2.0;
+    for (mut projectile, transform) in projectiles.iter_mut() {
+        if projectile.hit.is_some() {
+            continue;
+        }
+        let hit = players.iter().find(|(target, player, target_transform)| {
+            *target != projectile.owner
+                && player.state != PlayerState::Dead
+                && !player.invulnerable()
+                && can_hit(&projectile, player.team, &match_settings)
+                && (target_transform.translation.x - transform.translation.x).abs() < reach(player)
+        });
+        if let Some((target, player, _)) = hit {
+            ev_attack.send(AttackEvent {
+                attacker: projectile.owner,
+                origin: Some(transform.translation),
+                range: reach(player),
+                unparryable: false,
+            });
+            projectile.hit = Some(target);
+        }
+    }
+}
+
+/// Gone once the hit has been dealt with: it landed, or the fighter got out of it
+/// some other way.
+pub fn release_projectiles(
+    mut commands: Commands,
+    players: Query<&Player>,
+    projectiles: Query<(Entity, &Projectile)>,
+) {
+    for (entity, projectile) in projectiles.iter() {
+        let Some(target) = projectile.hit else {
+            continue;
+        };
+        let waiting = players.get(target).is_ok_and(|player| {
+            player.state == PlayerState::TakingDamage
+                && player.last_hit_by == Some(projectile.owner)
+        });
+        if !waiting {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn clear_projectiles_on_reset(
+    mut commands: Commands,
+    mut ev_reset: EventReader<ResetPlayers>,
+    projectiles: Query<Entity, With<Projectile>>,
+) {
+    if ev_reset.read().count() == 0 {
+        return;
+    }
+    for entity in projectiles.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn despawn_projectiles(mut commands: Commands, projectiles: Query<Entity, With<Projectile>>) {
+    for entity in projectiles.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{attack, player, projectile_app, spawn_fighter, step};
+
+    /// A projectile from `owner` about to reach a fighter at x = 500.
+    fn spawn_projectile(app: &mut App, owner: Entity, team: u8) -> Entity {
+        app.world
+            .spawn((
+                Projectile {
+                    owner,
+                    team,
+                    velocity: 900.0,
+                    lifetime: Timer::from_seconds(1.5, TimerMode::Once),
+                    hit: None,
+                },
+                Transform::from_xyz(460.0, 0.0, 0.0),
+            ))
+            .id()
+    }
+
+    #[test]
+    fn hit_by_projectile_then_attacking_back_reflects_it() {
+        let mut app = projectile_app();
+        let shooter = spawn_fighter(&mut app, 1, 0.0);
+        let defender = spawn_fighter(&mut app, 2, 500.0);
+        let projectile = spawn_projectile(&mut app, shooter, 1);
+
+        step(&mut app, 1);
+        assert_eq!(player(&app, defender).state, PlayerState::TakingDamage);
+        assert_eq!(
+            app.world.get::<Projectile>(projectile).unwrap().hit,
+            Some(defender)
+        );
+
+        // The shooter is far out of reach of the attack itself.
+        let reach = player(&app, defender).definition().combat.attack_range;
+        attack(&mut app, defender, reach);
+        step(&mut app, 1);
+        assert_eq!(player(&app, defender).state, PlayerState::Clashing);
+        assert_eq!(player(&app, defender).health, 100.0);
+        let reflected = app.world.get::<Projectile>(projectile).unwrap();
+        assert_eq!(reflected.owner, defender);
+        assert!(reflected.velocity < 0.0);
+
+        step(&mut app, 40);
+        let shooter = player(&app, shooter);
+        assert_eq!(shooter.last_hit_by, Some(defender));
+        assert!(shooter.health < 100.0);
+        assert_eq!(player(&app, defender).health, 100.0);
+    }
+
+    #[test]
+    fn unparried_projectile_hit_lands_and_is_gone() {
+        let mut app = projectile_app();
+        let shooter = spawn_fighter(&mut app, 1, 0.0);
+        let defender = spawn_fighter(&mut app, 2, 500.0);
+        let projectile = spawn_projectile(&mut app, shooter, 1);
+
+        step(&mut app, 10);
+        let hit = player(&app, defender);
+        assert_eq!(hit.state, PlayerState::Hitstun);
+        assert!(hit.health < 100.0);
+        assert!(app.world.get_entity(projectile).is_none());
+    }
+}
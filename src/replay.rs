@@ -0,0 +1,275 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clash::ClashPreset,
+    input::{InputSample, PlayerInput},
+    pause::not_paused,
+    player::{ClashCounter, Lineup, Player, PlayerState},
+    GameState, MatchMode, MatchSettings, Points,
+};
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<RecordedLine>()
+            .register_type::<ReplaySettings>()
+            .insert_resource(ReplaySettings::default())
+            .insert_resource(MatchRecording::default())
+            .add_systems(OnEnter(GameState::Match), start_recording)
+            .add_systems(
+                PostUpdate,
+                record_frame
+                    .run_if(in_state(GameState::Match))
+                    .run_if(not_paused),
+            )
+            .add_systems(Update, save_replay);
+    }
+}
+
+/// Bumped whenever a line changes shape, so old replays are rejected instead of misread.
+pub const REPLAY_VERSION: u32 = 5;
+
+#[derive(Debug, Resource, Reflect)]
+#[reflect(Resource)]
+pub struct ReplaySettings {
+    /// A full snapshot is stored every this many frames, so playback can start
+    /// anywhere and doesn't drift when frame times differ.
+    pub snapshot_frames: u32,
+}
+
+impl Default for ReplaySettings {
+    fn default() -> Self {
+        ReplaySettings { snapshot_frames: 6 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayFighter {
+    pub player_number: u8,
+    /// Index into [`crate::fighter::FIGHTERS`].
+    pub fighter: usize,
+    pub color: [f32; 4],
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayHeader {
+    pub version: u32,
+    pub mode: MatchMode,
+    pub clash_preset: ClashPreset,
+    pub fighters: Vec<ReplayFighter>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerSnapshot {
+    pub player_number: u8,
+    pub state: PlayerState,
+    pub position: (f32, f32),
+    pub attack_elapsed: f32,
+    pub parry_elapsed: f32,
+    pub clashing_elapsed: f32,
+    pub hitstun_elapsed: f32,
+    pub special_elapsed: f32,
+    pub throw_elapsed: f32,
+    pub dash_elapsed: f32,
+    pub velocity: f32,
+    pub health: f32,
+    pub meter: f32,
+    pub combo_hits: u32,
+    pub combo_damage: f32,
+    /// Player number of whoever hit this fighter last.
+    pub last_hit_by: Option<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub players: Vec<PlayerSnapshot>,
+    /// Scores by player number.
+    pub scores: Vec<(u8, u32)>,
+    pub clash_counter: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayFrame {
+    pub frame: u32,
+    /// Each fighter's input this frame, by player number.
+    pub inputs: Vec<(u8, InputSample)>,
+    pub snapshot: Option<Snapshot>,
+}
+
+/// A replay is a header line followed by one line per frame. The spectator stream
+/// sends exactly the same lines, so a spectated match can be saved as a replay.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ReplayLine {
+    Header(ReplayHeader),
+    Frame(ReplayFrame),
+}
+
+impl ReplayLine {
+    pub fn to_line(&self) -> String {
+        ron::to_string(self).expect("replay lines always serialize")
+    }
+
+    pub fn from_line(line: &str) -> Result<ReplayLine, ron::error::SpannedError> {
+        ron::from_str(line)
+    }
+}
+
+/// Sent for every line added to the [`MatchRecording`], for anything streaming the match out.
+#[derive(Event)]
+pub struct RecordedLine(pub ReplayLine);
+
+/// The match being played or spectated, in replay form.
+#[derive(Debug, Default, Resource)]
+pub struct MatchRecording {
+    pub header: Option<ReplayHeader>,
+    pub frames: Vec<ReplayFrame>,
+}
+
+impl MatchRecording {
+    pub fn start(&mut self, header: ReplayHeader) {
+        self.header = Some(header);
+        self.frames.clear();
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        if let Some(header) = &self.header {
+            text.push_str(&ReplayLine::Header(header.clone()).to_line());
+            text.push('\n');
+        }
+        for frame in self.frames.iter() {
+            text.push_str(&ReplayLine::Frame(frame.clone()).to_line());
+            text.push('\n');
+        }
+        text
+    }
+}
+
+pub fn snapshot(
+    players: &Query<(Entity, &Player, &Transform)>,
+    points: &Points,
+    clash_counter: &ClashCounter,
+) -> Snapshot {
+    let player_number = |entity: Entity| {
+        players
+            .get(entity)
+            .ok()
+            .map(|(_, player, _)| player.player_number)
+    };
+
+    let mut snapshot = Snapshot {
+        players: players
+            .iter()
+            .map(|(_, player, transform)| PlayerSnapshot {
+                player_number: player.player_number,
+                state: player.state,
+                position: (transform.translation.x, transform.translation.y),
+                attack_elapsed: player.attack_timer.elapsed_secs(),
+                parry_elapsed: player.parry_timer.elapsed_secs(),
+                clashing_elapsed: player.clashing_timer.elapsed_secs(),
+                hitstun_elapsed: player.hitstun_timer.elapsed_secs(),
+                special_elapsed: player.special_timer.elapsed_secs(),
+                throw_elapsed: player.throw_timer.elapsed_secs(),
+                dash_elapsed: player.dash_timer.elapsed_secs(),
+                velocity: player.velocity,
+                health: player.health,
+                meter: player.meter,
+                combo_hits: player.combo_hits,
+                combo_damage: player.combo_damage,
+                last_hit_by: player.last_hit_by.and_then(player_number),
+            })
+            .collect(),
+        scores: points
+            .scores
+            .iter()
+            .filter_map(|(entity, score)| Some((player_number(*entity)?, *score)))
+            .collect(),
+        clash_counter: clash_counter.0,
+    };
+    snapshot.players.sort_by_key(|player| player.player_number);
+    snapshot.scores.sort();
+    snapshot
+}
+
+fn start_recording(
+    lineup: Res<Lineup>,
+    match_settings: Res<MatchSettings>,
+    mut recording: ResMut<MatchRecording>,
+    mut ev_recorded: EventWriter<RecordedLine>,
+) {
+    let header = ReplayHeader {
+        version: REPLAY_VERSION,
+        mode: match_settings.mode,
+        clash_preset: match_settings.clash_preset,
+        fighters: lineup
+            .0
+            .iter()
+            .map(|entry| ReplayFighter {
+                player_number: entry.player_number,
+                fighter: entry.fighter,
+                color: entry.color.as_rgba_f32(),
+            })
+            .collect(),
+    };
+    recording.start(header.clone());
+    ev_recorded.send(RecordedLine(ReplayLine::Header(header)));
+}
+
+pub fn record_frame(
+    settings: Res<ReplaySettings>,
+    mut recording: ResMut<MatchRecording>,
+    mut ev_recorded: EventWriter<RecordedLine>,
+    inputs: Query<(&Player, &PlayerInput)>,
+    players: Query<(Entity, &Player, &Transform)>,
+    points: Res<Points>,
+    clash_counter: Res<ClashCounter>,
+) {
+    let frame = recording.frames.len() as u32;
+    let mut frame_inputs: Vec<_> = inputs
+        .iter()
+        .map(|(player, input)| (player.player_number, input.current))
+        .collect();
+    frame_inputs.sort_by_key(|(player_number, _)| *player_number);
+
+    let replay_frame = ReplayFrame {
+        frame,
+        inputs: frame_inputs,
+        snapshot: (frame.rem_euclid(settings.snapshot_frames.max(1)) == 0)
+            .then(|| snapshot(&players, &points, &clash_counter)),
+    };
+    recording.frames.push(replay_frame.clone());
+    ev_recorded.send(RecordedLine(ReplayLine::Frame(replay_frame)));
+}
+
+fn save_replay(keyboard_input: Res<ButtonInput<KeyCode>>, recording: Res<MatchRecording>) {
+    if !keyboard_input.just_pressed(KeyCode::F7) {
+        return;
+    }
+    if recording.header.is_none() {
+        println!("Nothing recorded yet");
+        return;
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let seconds = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        let path = std::path::Path::new("replays").join(format!("replay-{}.ron", seconds));
+        let result = std::fs::create_dir_all("replays")
+            .and_then(|_| std::fs::write(&path, recording.to_text()));
+        match result {
+            Ok(()) => println!(
+                "Saved replay of {} frames to {}",
+                recording.frames.len(),
+                path.display()
+            ),
+            Err(err) => println!("Couldn't save replay to {}: {}", path.display(), err),
+        }
+    }
+    #[cfg(target_arch = "wasm32")]
+    println!("Saving replays isn't supported on the web build");
+}
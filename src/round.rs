@@ -0,0 +1,465 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{
+    combo::ComboHit,
+    input::{InputSet, PlayerInput},
+    pause::not_paused,
+    player::{frames_to_secs, Player, PlayerState, PlayerStateChangeEvent, ResetPlayers},
+    profile::PlayerProfile,
+    training::TrainingMode,
+    GameState, MatchMode, MatchSettings, RoundOver,
+};
+
+/// Every round opens with "3-2-1-Fight!" and closes with a K.O. in slow motion (or the
+/// judges' call when the clock runs out) and a winner announcement, after which the
+/// fighters are reset. The sequence is timed on the game clock with the slow motion
+/// taken out, so pausing holds it and the K.O. doesn't stretch it.
+pub struct RoundPlugin;
+
+impl Plugin for RoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<RoundEnded>()
+            .register_type::<RoundTiming>()
+            .insert_resource(RoundTiming::default())
+            .insert_resource(RoundSequence::default())
+            .insert_resource(RoundClock::default())
+            .insert_resource(RoundStats::default())
+            .add_systems(
+                OnEnter(GameState::Match),
+                (spawn_round_banner, start_first_round),
+            )
+            .add_systems(OnExit(GameState::Match), end_round_sequence)
+            .add_systems(
+                Update,
+                (
+                    (track_round_stats, tick_round_clock)
+                        .before(crate::score_point)
+                        .run_if(not_paused),
+                    (follow_round_events.after(crate::score_point), advance_round)
+                        .chain()
+                        .run_if(not_paused),
+                    lock_inputs
+                        .after(InputSet::Sample)
+                        .before(InputSet::Override)
+                        .run_if(not_paused),
+                    update_round_banner,
+                )
+                    .run_if(in_state(GameState::Match)),
+            );
+    }
+}
+
+/// Sent when the winner announcement is over, just before the fighters are reset.
+#[derive(Event)]
+pub struct RoundEnded;
+
+/// Lengths of each part of the sequence, in 60 Hz frames.
+#[derive(Debug, Resource, Reflect)]
+#[reflect(Resource)]
+pub struct RoundTiming {
+    /// How long each of 3, 2 and 1 is shown.
+    pub countdown_step_frames: u32,
+    /// How long "Fight!" stays up once the fighters can move.
+    pub fight_banner_frames: u32,
+    pub knockout_frames: u32,
+    pub announce_frames: u32,
+    /// How fast the game runs during the K.O.
+    pub knockout_speed: f32,
+}
+
+impl Default for RoundTiming {
+    fn default() -> Self {
+        RoundTiming {
+            countdown_step_frames: 40,
+            fight_banner_frames: 30,
+            knockout_frames: 60,
+            announce_frames: 90,
+            knockout_speed: 0.25,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RoundPhase {
+    #[default]
+    Countdown,
+    Fighting,
+    KnockOut,
+    Announce,
+}
+
+#[derive(Debug, Default, Resource)]
+pub struct RoundSequence {
+    pub phase: RoundPhase,
+    /// Seconds since the phase began, on the game clock but at full speed.
+    pub elapsed: f32,
+    winner_text: String,
+    /// The round ran out of time rather than ending in a K.O.
+    time_over: bool,
+}
+
+impl RoundSequence {
+    /// Whether fighters are free to act and a death can still decide the round.
+    pub fn is_live(&self) -> bool {
+        self.phase == RoundPhase::Fighting
+    }
+
+    fn enter(&mut self, phase: RoundPhase) {
+        self.phase = phase;
+        self.elapsed = 0.0;
+    }
+}
+
+/// Time left in the round, if rounds have a limit. It only runs while the round is live.
+#[derive(Debug, Default, Resource)]
+pub struct RoundClock(pub Option<Timer>);
+
+impl RoundClock {
+    fn start(&mut self, match_settings: &MatchSettings, training: &TrainingMode) {
+        self.0 = (match_settings.round_seconds > 0 && !training.enabled)
+            .then(|| Timer::from_seconds(match_settings.round_seconds as f32, TimerMode::Once));
+    }
+
+    pub fn expired(&self) -> bool {
+        self.0.as_ref().is_some_and(Timer::just_finished)
+    }
+}
+
+/// What each fighter did this round, for judging a time over.
+#[derive(Debug, Default, Resource)]
+pub struct RoundStats {
+    hits: HashMap<Entity, u32>,
+    parries: HashMap<Entity, u32>,
+    states: HashMap<Entity, PlayerState>,
+}
+
+impl RoundStats {
+    pub fn hits(&self, entity: Entity) -> u32 {
+        self.hits.get(&entity).copied().unwrap_or_default()
+    }
+
+    pub fn parries(&self, entity: Entity) -> u32 {
+        self.parries.get(&entity).copied().unwrap_or_default()
+    }
+
+    fn clear(&mut self) {
+        self.hits.clear();
+        self.parries.clear();
+    }
+}
+
+#[derive(Component)]
+struct RoundBannerRoot;
+
+#[derive(Component)]
+struct RoundBanner;
+
+/// Training resets happen all the time, so they skip the countdown.
+fn opening_phase(training: &TrainingMode) -> RoundPhase {
+    if training.enabled {
+        RoundPhase::Fighting
+    } else {
+        RoundPhase::Countdown
+    }
+}
+
+fn start_first_round(
+    mut round: ResMut<RoundSequence>,
+    mut clock: ResMut<RoundClock>,
+    mut stats: ResMut<RoundStats>,
+    training: Res<TrainingMode>,
+    match_settings: Res<MatchSettings>,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    round.enter(opening_phase(&training));
+    clock.start(&match_settings, &training);
+    stats.clear();
+    time.set_relative_speed(1.0);
+}
+
+fn end_round_sequence(
+    mut commands: Commands,
+    mut round: ResMut<RoundSequence>,
+    mut time: ResMut<Time<Virtual>>,
+    banners: Query<Entity, With<RoundBannerRoot>>,
+) {
+    round.enter(RoundPhase::Countdown);
+    time.set_relative_speed(1.0);
+    for entity in banners.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn winner_text(
+    round: &RoundOver,
+    match_settings: &MatchSettings,
+    players: &Query<(&Player, Option<&PlayerProfile>)>,
+) -> String {
+    let winners: Vec<_> = players.iter_many(round.winners.iter()).collect();
+    match (winners.first(), match_settings.mode) {
+        (None, _) => "Draw".to_string(),
+        (Some((player, _)), MatchMode::Teams) => format!("Team {} wins!", player.team),
+        (Some((_, Some(profile))), MatchMode::FreeForAll) => format!("{} wins!", profile.0),
+        (Some((player, None)), MatchMode::FreeForAll) => {
+            format!("Player {} wins!", player.player_number)
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn follow_round_events(
+    mut ev_round_over: EventReader<RoundOver>,
+    mut ev_reset: EventReader<ResetPlayers>,
+    mut round: ResMut<RoundSequence>,
+    timing: Res<RoundTiming>,
+    training: Res<TrainingMode>,
+    match_settings: Res<MatchSettings>,
+    players: Query<(&Player, Option<&PlayerProfile>)>,
+    mut clock: ResMut<RoundClock>,
+    mut stats: ResMut<RoundStats>,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    if ev_reset.read().count() > 0 {
+        round.enter(opening_phase(&training));
+        clock.start(&match_settings, &training);
+        stats.clear();
+        time.set_relative_speed(1.0);
+    }
+    for round_over in ev_round_over.read() {
+        round.winner_text = winner_text(round_over, &match_settings, &players);
+        round.time_over = round_over.time_over;
+        round.enter(RoundPhase::KnockOut);
+        if !round_over.time_over {
+            time.set_relative_speed(timing.knockout_speed);
+        }
+    }
+}
+
+fn track_round_stats(
+    mut ev_player_state_change: EventReader<PlayerStateChangeEvent>,
+    mut ev_combo_hit: EventReader<ComboHit>,
+    players: Query<&Player>,
+    mut stats: ResMut<RoundStats>,
+) {
+    for event in ev_player_state_change.read() {
+        let (entity, to) = (event.0, event.1);
+        let from = stats.states.insert(entity, to).unwrap_or_default();
+        match (from, to) {
+            (_, PlayerState::TakingDamage) => {
+                if let Some(attacker) = players
+                    .get(entity)
+                    .ok()
+                    .and_then(|player| player.last_hit_by)
+                {
+                    *stats.hits.entry(attacker).or_default() += 1;
+                }
+            }
+            (PlayerState::TakingDamage, PlayerState::Clashing) => {
+                *stats.parries.entry(entity).or_default() += 1;
+            }
+            _ => {}
+        }
+    }
+    // Follow-ups in a combo land without a state change.
+    for combo_hit in ev_combo_hit.read() {
+        *stats.hits.entry(combo_hit.attacker).or_default() += 1;
+    }
+}
+
+fn tick_round_clock(
+    time: Res<Time>,
+    round: Res<RoundSequence>,
+    training: Res<TrainingMode>,
+    mut clock: ResMut<RoundClock>,
+) {
+    if !round.is_live() || training.enabled {
+        return;
+    }
+    if let Some(timer) = clock.0.as_mut() {
+        timer.tick(time.delta());
+    }
+}
+
+/// How far the game clock moved this update, undoing any slow motion. Nothing while paused.
+fn full_speed_delta(time: &Time<Virtual>) -> f32 {
+    let speed = time.effective_speed();
+    if speed > 0.0 {
+        time.delta_seconds() / speed
+    } else {
+        0.0
+    }
+}
+
+fn advance_round(
+    mut round: ResMut<RoundSequence>,
+    timing: Res<RoundTiming>,
+    mut time: ResMut<Time<Virtual>>,
+    mut ev_reset: EventWriter<ResetPlayers>,
+    mut ev_round_ended: EventWriter<RoundEnded>,
+) {
+    round.elapsed += full_speed_delta(&time);
+    let past = |frames: u32| round.elapsed >= frames_to_secs(frames);
+    match round.phase {
+        RoundPhase::Countdown if past(timing.countdown_step_frames * 3) => {
+            round.enter(RoundPhase::Fighting);
+        }
+        RoundPhase::KnockOut if past(timing.knockout_frames) => {
+            time.set_relative_speed(1.0);
+            round.enter(RoundPhase::Announce);
+        }
+        RoundPhase::Announce if past(timing.announce_frames) => {
+            println!("Resetting game");
+            ev_round_ended.send(RoundEnded);
+            ev_reset.send(ResetPlayers);
+            round.enter(RoundPhase::Countdown);
+        }
+        _ => {}
+    }
+}
+
+/// Fighters stand still until the round is live. Runs before `Override`, so the
+/// training dummy and spectated inputs are untouched.
+fn lock_inputs(round: Res<RoundSequence>, mut inputs: Query<&mut PlayerInput>) {
+    if round.is_live() {
+        return;
+    }
+    for mut input in inputs.iter_mut() {
+        input.set_current(default());
+    }
+}
+
+fn spawn_round_banner(mut commands: Commands) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            z_index: ZIndex::Global(5),
+            ..default()
+        })
+        .insert(RoundBannerRoot)
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle {
+                    text: Text {
+                        sections: vec![TextSection {
+                            value: String::new(),
+                            style: TextStyle {
+                                font_size: 120.0,
+                                color: Color::WHITE,
+                                font: Default::default(),
+                            },
+                        }],
+                        justify: JustifyText::Center,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(RoundBanner);
+        });
+}
+
+fn update_round_banner(
+    round: Res<RoundSequence>,
+    timing: Res<RoundTiming>,
+    mut banner: Query<&mut Text, With<RoundBanner>>,
+) {
+    let Ok(mut text) = banner.get_single_mut() else {
+        return;
+    };
+    let value = match round.phase {
+        RoundPhase::Countdown => {
+            let step = (round.elapsed / frames_to_secs(timing.countdown_step_frames.max(1))) as u32;
+            (3 - step.min(2)).to_string()
+        }
+        RoundPhase::Fighting if round.elapsed < frames_to_secs(timing.fight_banner_frames) => {
+            "Fight!".to_string()
+        }
+        RoundPhase::Fighting => String::new(),
+        RoundPhase::KnockOut if round.time_over => "Time!".to_string(),
+        RoundPhase::KnockOut => "K.O.".to_string(),
+        RoundPhase::Announce => round.winner_text.clone(),
+    };
+    if text.sections[0].value != value {
+        text.sections[0].value = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::{TimePlugin, TimeUpdateStrategy};
+
+    use super::*;
+
+    /// The real clock moves one 60 Hz frame per update.
+    fn round_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(TimePlugin)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+                frames_to_secs(1),
+            )))
+            .add_event::<ResetPlayers>()
+            .add_event::<RoundEnded>()
+            .insert_resource(RoundTiming::default())
+            .insert_resource(RoundSequence::default())
+            .add_systems(Update, advance_round);
+        // The first update only starts the clock.
+        app.update();
+        app
+    }
+
+    fn step(app: &mut App, frames: u32) {
+        for _ in 0..frames {
+            app.update();
+        }
+    }
+
+    #[test]
+    fn knockout_slow_motion_does_not_stretch_the_sequence() {
+        let mut app = round_app();
+        app.world
+            .resource_mut::<RoundSequence>()
+            .enter(RoundPhase::KnockOut);
+        app.world
+            .resource_mut::<Time<Virtual>>()
+            .set_relative_speed(0.25);
+
+        let knockout_frames = app.world.resource::<RoundTiming>().knockout_frames;
+        step(&mut app, knockout_frames - 1);
+        assert_eq!(
+            app.world.resource::<RoundSequence>().phase,
+            RoundPhase::KnockOut
+        );
+        step(&mut app, 2);
+        assert_eq!(
+            app.world.resource::<RoundSequence>().phase,
+            RoundPhase::Announce
+        );
+        assert_eq!(app.world.resource::<Time<Virtual>>().relative_speed(), 1.0);
+    }
+
+    #[test]
+    fn pausing_holds_the_countdown() {
+        let mut app = round_app();
+        app.world.resource_mut::<Time<Virtual>>().pause();
+        step(&mut app, 600);
+        let round = app.world.resource::<RoundSequence>();
+        assert_eq!(round.phase, RoundPhase::Countdown);
+        assert_eq!(round.elapsed, 0.0);
+
+        app.world.resource_mut::<Time<Virtual>>().unpause();
+        let countdown_frames = app.world.resource::<RoundTiming>().countdown_step_frames * 3;
+        step(&mut app, countdown_frames + 1);
+        assert_eq!(
+            app.world.resource::<RoundSequence>().phase,
+            RoundPhase::Fighting
+        );
+    }
+}
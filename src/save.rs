@@ -0,0 +1,354 @@
+use bevy::{
+    prelude::*,
+    window::{PresentMode, PrimaryWindow, WindowMode},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clash::ClashPreset,
+    fighter::PaletteSet,
+    input::{InputBufferSettings, KEYBOARD_LAYOUTS},
+    player::{ClashCounter, PlayerState, PlayerStateChangeEvent},
+    profile::Profile,
+    theme::ThemePreset,
+    tournament::MatchResult,
+    ControlPerPlayer, GameState, MatchMode, MatchSettings,
+};
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        let (save, writable) = load();
+        app.register_type::<SaveData>()
+            .insert_resource(save)
+            .insert_resource(SaveStore { writable })
+            .add_systems(Startup, apply_saved_options)
+            .add_systems(OnEnter(GameState::Match), count_match)
+            .add_systems(
+                Update,
+                (
+                    toggle_fullscreen,
+                    store_options,
+                    count_knockouts_and_clashes.run_if(in_state(GameState::Match)),
+                    apply_video_options,
+                    write_save,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Bumped whenever a field is renamed or changes meaning. Added fields don't need a
+/// bump: anything missing from an older file is filled in from `Default`.
+pub const SAVE_VERSION: u32 = 3;
+
+#[derive(Debug, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioOptions {
+    /// Nothing plays sound yet; kept so the option survives until something does.
+    pub master_volume: f32,
+}
+
+impl Default for AudioOptions {
+    fn default() -> Self {
+        AudioOptions { master_volume: 1.0 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoOptions {
+    pub vsync: bool,
+    pub fullscreen: bool,
+    pub theme: ThemePreset,
+    pub palettes: PaletteSet,
+    /// On top of the scaling that follows the window size.
+    pub ui_scale: f32,
+    /// A font under `assets/`, e.g. `fonts/MyFont.ttf`. Bevy's built-in font if unset.
+    pub font: Option<String>,
+}
+
+impl Default for VideoOptions {
+    fn default() -> Self {
+        VideoOptions {
+            vsync: false,
+            fullscreen: false,
+            theme: ThemePreset::default(),
+            palettes: PaletteSet::default(),
+            ui_scale: 1.0,
+            font: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameplayOptions {
+    pub clash_preset: ClashPreset,
+    pub mode: MatchMode,
+    pub friendly_fire: bool,
+    pub round_seconds: u32,
+    pub attack_buffer_frames: u32,
+    pub parry_buffer_frames: u32,
+}
+
+impl Default for GameplayOptions {
+    fn default() -> Self {
+        let match_settings = MatchSettings::default();
+        let buffer = InputBufferSettings::default();
+        GameplayOptions {
+            clash_preset: match_settings.clash_preset,
+            mode: match_settings.mode,
+            friendly_fire: match_settings.friendly_fire,
+            round_seconds: match_settings.round_seconds,
+            attack_buffer_frames: buffer.attack_frames,
+            parry_buffer_frames: buffer.parry_frames,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LifetimeStats {
+    pub matches_played: u32,
+    pub knockouts: u32,
+    pub clashes: u32,
+}
+
+/// Everything that outlives a run: options, key bindings and lifetime stats.
+#[derive(Debug, Clone, PartialEq, Resource, Reflect, Serialize, Deserialize)]
+#[reflect(Resource)]
+#[serde(default)]
+pub struct SaveData {
+    pub version: u32,
+    /// The four keyboard layouts the lobby offers, in join order.
+    pub keyboard_layouts: [ControlPerPlayer; 4],
+    pub audio: AudioOptions,
+    pub video: VideoOptions,
+    pub gameplay: GameplayOptions,
+    pub stats: LifetimeStats,
+    pub profiles: Vec<Profile>,
+    /// Tournament matches between profiles, oldest first; ratings are worked out from these.
+    pub match_history: Vec<MatchResult>,
+}
+
+impl Default for SaveData {
+    fn default() -> Self {
+        SaveData {
+            version: SAVE_VERSION,
+            keyboard_layouts: KEYBOARD_LAYOUTS,
+            audio: AudioOptions::default(),
+            video: VideoOptions::default(),
+            gameplay: GameplayOptions::default(),
+            stats: LifetimeStats::default(),
+            profiles: Vec::new(),
+            match_history: Vec::new(),
+        }
+    }
+}
+
+/// Whether the save on disk may be overwritten. It may not when it came from a
+/// newer build, so running an old build can't throw away newer data.
+#[derive(Debug, Resource)]
+struct SaveStore {
+    writable: bool,
+}
+
+#[derive(Deserialize)]
+struct SaveVersion {
+    #[serde(default)]
+    version: u32,
+}
+
+/// Reads a save of any version this build knows, upgrading it to [`SAVE_VERSION`].
+fn migrate(text: &str) -> Result<SaveData, String> {
+    let version = ron::from_str::<SaveVersion>(text)
+        .map_err(|err| err.to_string())?
+        .version;
+    let mut save = match version {
+        // Older layouts get their own structs here, converted into the current one.
+        1 | 2 => {
+            // Keyboard layouts had no throw key yet, and before version 2 no special key.
+            let mut save = ron::from_str::<SaveData>(text).map_err(|err| err.to_string())?;
+            for (keys, default) in save.keyboard_layouts.iter_mut().zip(KEYBOARD_LAYOUTS) {
+                if version < 2 {
+                    keys.special = default.special;
+                }
+                keys.throw = default.throw;
+            }
+            save
+        }
+        SAVE_VERSION => ron::from_str::<SaveData>(text).map_err(|err| err.to_string())?,
+        _ => return Err(format!("unknown save version {}", version)),
+    };
+    save.version = SAVE_VERSION;
+    Ok(save)
+}
+
+/// Returns the save and whether it's safe to write back.
+fn load() -> (SaveData, bool) {
+    let Some(text) = storage::read() else {
+        return (SaveData::default(), true);
+    };
+    match migrate(&text) {
+        Ok(save) => (save, true),
+        Err(err) => {
+            let newer =
+                ron::from_str::<SaveVersion>(&text).is_ok_and(|save| save.version > SAVE_VERSION);
+            println!("Couldn't load saved settings, using defaults: {}", err);
+            (SaveData::default(), !newer)
+        }
+    }
+}
+
+fn apply_saved_options(
+    save: Res<SaveData>,
+    mut match_settings: ResMut<MatchSettings>,
+    mut buffer: ResMut<InputBufferSettings>,
+) {
+    match_settings.clash_preset = save.gameplay.clash_preset;
+    match_settings.mode = save.gameplay.mode;
+    match_settings.friendly_fire = save.gameplay.friendly_fire;
+    match_settings.round_seconds = save.gameplay.round_seconds;
+    buffer.attack_frames = save.gameplay.attack_buffer_frames;
+    buffer.parry_frames = save.gameplay.parry_buffer_frames;
+}
+
+fn toggle_fullscreen(keyboard_input: Res<ButtonInput<KeyCode>>, mut save: ResMut<SaveData>) {
+    if keyboard_input.just_pressed(KeyCode::F11) {
+        save.video.fullscreen = !save.video.fullscreen;
+    }
+}
+
+/// Copies options changed elsewhere into the save.
+fn store_options(
+    match_settings: Res<MatchSettings>,
+    buffer: Res<InputBufferSettings>,
+    mut save: ResMut<SaveData>,
+) {
+    if !match_settings.is_changed() && !buffer.is_changed() {
+        return;
+    }
+    let gameplay = GameplayOptions {
+        clash_preset: match_settings.clash_preset,
+        mode: match_settings.mode,
+        friendly_fire: match_settings.friendly_fire,
+        round_seconds: match_settings.round_seconds,
+        attack_buffer_frames: buffer.attack_frames,
+        parry_buffer_frames: buffer.parry_frames,
+    };
+    if save.gameplay != gameplay {
+        save.gameplay = gameplay;
+    }
+}
+
+fn count_match(mut save: ResMut<SaveData>) {
+    save.stats.matches_played += 1;
+}
+
+fn count_knockouts_and_clashes(
+    mut ev_player_state_change: EventReader<PlayerStateChangeEvent>,
+    clash_counter: Res<ClashCounter>,
+    mut last_clash_count: Local<u32>,
+    mut save: ResMut<SaveData>,
+) {
+    let knockouts = ev_player_state_change
+        .read()
+        .filter(|event| event.1 == PlayerState::Dead)
+        .count() as u32;
+    if knockouts > 0 {
+        save.stats.knockouts += knockouts;
+    }
+
+    if clash_counter.is_changed() {
+        if clash_counter.0 > *last_clash_count {
+            save.stats.clashes += clash_counter.0 - *last_clash_count;
+        }
+        *last_clash_count = clash_counter.0;
+    }
+}
+
+fn apply_video_options(save: Res<SaveData>, mut windows: Query<&mut Window, With<PrimaryWindow>>) {
+    if !save.is_changed() {
+        return;
+    }
+    for mut window in windows.iter_mut() {
+        let present_mode = if save.video.vsync {
+            PresentMode::AutoVsync
+        } else {
+            PresentMode::AutoNoVsync
+        };
+        let mode = if save.video.fullscreen {
+            WindowMode::BorderlessFullscreen
+        } else {
+            WindowMode::Windowed
+        };
+        if window.present_mode != present_mode {
+            window.present_mode = present_mode;
+        }
+        if window.mode != mode {
+            window.mode = mode;
+        }
+    }
+}
+
+fn write_save(save: Res<SaveData>, store: Res<SaveStore>) {
+    if !save.is_changed() || save.is_added() || !store.writable {
+        return;
+    }
+    let text = match ron::ser::to_string_pretty(&*save, ron::ser::PrettyConfig::default()) {
+        Ok(text) => text,
+        Err(err) => {
+            println!("Couldn't serialize settings: {}", err);
+            return;
+        }
+    };
+    if let Err(err) = storage::write(&text) {
+        println!("Couldn't save settings: {}", err);
+    }
+}
+
+/// A RON file in the platform config dir.
+#[cfg(not(target_arch = "wasm32"))]
+mod storage {
+    use std::path::PathBuf;
+
+    fn path() -> Option<PathBuf> {
+        directories::ProjectDirs::from("", "", "bevy-fighting-game")
+            .map(|dirs| dirs.config_dir().join("save.ron"))
+    }
+
+    pub fn read() -> Option<String> {
+        std::fs::read_to_string(path()?).ok()
+    }
+
+    pub fn write(text: &str) -> Result<(), String> {
+        let path = path().ok_or("no config directory on this platform")?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        std::fs::write(&path, text).map_err(|err| err.to_string())
+    }
+}
+
+/// The same RON text, kept in the browser's `localStorage`.
+#[cfg(target_arch = "wasm32")]
+mod storage {
+    const KEY: &str = "bevy-fighting-game/save";
+
+    fn local_storage() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok()?
+    }
+
+    pub fn read() -> Option<String> {
+        local_storage()?.get_item(KEY).ok()?
+    }
+
+    pub fn write(text: &str) -> Result<(), String> {
+        local_storage()
+            .ok_or("localStorage is unavailable")?
+            .set_item(KEY, text)
+            .map_err(|err| format!("{:?}", err))
+    }
+}
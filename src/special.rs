@@ -0,0 +1,223 @@
+use std::time::Duration;
+
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{
+    combo::{ComboEnded, ComboHit},
+    input::{InputSet, PlayerInput},
+    pause::not_paused,
+    player::{
+        combo_ended, facing, frames_to_secs, nearest_opponent_x, AttackEvent, Player, PlayerState,
+        PlayerStateChangeEvent,
+    },
+    projectile::SpawnProjectile,
+    state_machine::PlayerStateMachine,
+    GameState, MatchSettings,
+};
+
+/// Meter fills from landing hits, parrying and clashing, and the special button spends
+/// it. Special alone is a reversal, special towards the nearest opponent a long-range
+/// strike and special away from them a projectile, for fighters that have those. While
+/// being hit the button always means a reversal, which is the one way out of a combo.
+pub struct SpecialPlugin;
+
+impl Plugin for SpecialPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<MeterRules>()
+            .insert_resource(MeterRules::default())
+            .add_systems(
+                Update,
+                (gain_meter, perform_specials.after(InputSet::Override))
+                    .run_if(in_state(GameState::Match))
+                    .run_if(not_paused),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+pub enum SpecialMove {
+    Reversal,
+    LongStrike,
+    Projectile,
+}
+
+impl SpecialMove {
+    fn state(self) -> PlayerState {
+        match self {
+            SpecialMove::Reversal => PlayerState::Reversal,
+            SpecialMove::LongStrike => PlayerState::LongStrike,
+            SpecialMove::Projectile => PlayerState::Firing,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Reflect)]
+pub struct SpecialMoveRules {
+    pub cost: f32,
+    /// How close a fighter has to be to get hit.
+    pub range: f32,
+    /// How long the move lasts, recovery included.
+    pub frames: u32,
+    /// Lands straight away, with no parry window.
+    pub unparryable: bool,
+}
+
+/// How meter is earned. What the moves cost is part of each fighter's
+/// [`crate::fighter::Moveset`].
+#[derive(Debug, Resource, Clone, Reflect)]
+#[reflect(Resource)]
+pub struct MeterRules {
+    pub max_meter: f32,
+    /// Gained by the attacker for every hit that lands.
+    pub per_hit: f32,
+    pub per_parry: f32,
+    /// Gained by both fighters.
+    pub per_clash: f32,
+}
+
+impl Default for MeterRules {
+    fn default() -> Self {
+        MeterRules {
+            max_meter: 100.0,
+            per_hit: 15.0,
+            per_parry: 25.0,
+            per_clash: 10.0,
+        }
+    }
+}
+
+fn add_meter(player: &mut Player, amount: f32, rules: &MeterRules) {
+    player.meter = (player.meter + amount).min(rules.max_meter);
+}
+
+fn gain_meter(
+    mut ev_player_state_change: EventReader<PlayerStateChangeEvent>,
+    mut ev_combo_hit: EventReader<ComboHit>,
+    mut players: Query<&mut Player>,
+    rules: Res<MeterRules>,
+    mut states: Local<HashMap<Entity, PlayerState>>,
+) {
+    // Fighters are despawned at the end of every match.
+    states.retain(|entity, _| players.contains(*entity));
+
+    for event in ev_player_state_change.read() {
+        let (entity, to) = (event.0, event.1);
+        let from = states.insert(entity, to).unwrap_or_default();
+        match (from, to) {
+            (PlayerState::TakingDamage, PlayerState::Clashing) => {
+                if let Ok(mut player) = players.get_mut(entity) {
+                    add_meter(&mut player, rules.per_parry + rules.per_clash, &rules);
+                }
+            }
+            (_, PlayerState::Clashing) => {
+                if let Ok(mut player) = players.get_mut(entity) {
+                    add_meter(&mut player, rules.per_clash, &rules);
+                }
+            }
+            // The first hit of a combo; the rest come as `ComboHit`s.
+            (from, PlayerState::Hitstun | PlayerState::Dead) if from != PlayerState::Hitstun => {
+                let attacker = players
+                    .get(entity)
+                    .ok()
+                    .and_then(|player| player.last_hit_by);
+                if let Some(mut attacker) = attacker.and_then(|entity| players.get_mut(entity).ok())
+                {
+                    add_meter(&mut attacker, rules.per_hit, &rules);
+                }
+            }
+            _ => {}
+        }
+    }
+    for combo_hit in ev_combo_hit.read() {
+        if let Ok(mut attacker) = players.get_mut(combo_hit.attacker) {
+            add_meter(&mut attacker, rules.per_hit, &rules);
+        }
+    }
+}
+
+/// Which special a press asks for: towards the nearest opponent is a long-range strike,
+/// away from them a projectile, and neither a reversal.
+fn read_command(input: &PlayerInput, facing: f32) -> SpecialMove {
+    let (forward, back) = if facing > 0.0 {
+        (input.current.right, input.current.left)
+    } else {
+        (input.current.left, input.current.right)
+    };
+    match (forward, back) {
+        (true, false) => SpecialMove::LongStrike,
+        (false, true) => SpecialMove::Projectile,
+        _ => SpecialMove::Reversal,
+    }
+}
+
+fn perform_specials(
+    mut players: Query<(Entity, &mut Player, &PlayerInput, &Transform)>,
+    mut state_machine: PlayerStateMachine,
+    mut ev_attack: EventWriter<AttackEvent>,
+    mut ev_combo_ended: EventWriter<ComboEnded>,
+    mut ev_projectile: EventWriter<SpawnProjectile>,
+    match_settings: Res<MatchSettings>,
+) {
+    let fighters: Vec<(Entity, u8, f32)> = players
+        .iter()
+        .filter(|(_, player, ..)| player.state != PlayerState::Dead)
+        .map(|(entity, player, _, transform)| (entity, player.team, transform.translation.x))
+        .collect();
+
+    for (entity, mut player, input, transform) in players.iter_mut() {
+        if !input.special_just_pressed() {
+            continue;
+        }
+        let x = transform.translation.x;
+        let opponent_x = nearest_opponent_x(
+            &fighters,
+            entity,
+            player.team,
+            x,
+            match_settings.friendly_fire,
+        );
+        let facing = facing(x, opponent_x);
+        let special = match player.state {
+            PlayerState::TakingDamage | PlayerState::Hitstun => SpecialMove::Reversal,
+            PlayerState::Alive => read_command(input, facing),
+            _ => continue,
+        };
+        let Some(special_rules) = player.definition().moves.special(special) else {
+            continue;
+        };
+        if player.meter < special_rules.cost {
+            continue;
+        }
+
+        let ended = combo_ended(entity, &player);
+        let from = player.state;
+        player
+            .special_timer
+            .set_duration(Duration::from_secs_f32(frames_to_secs(
+                special_rules.frames,
+            )));
+        player.special_timer.reset();
+        if !state_machine.transition(entity, &mut player, special.state()) {
+            continue;
+        }
+        if from == PlayerState::Hitstun {
+            ev_combo_ended.send(ended);
+        }
+        player.meter -= special_rules.cost;
+        player.attack_timer.reset();
+        println!("Player {:?} used {:?}!", player.player_number, special);
+        if special == SpecialMove::Projectile {
+            ev_projectile.send(SpawnProjectile {
+                owner: entity,
+                direction: facing,
+            });
+        } else {
+            ev_attack.send(AttackEvent {
+                attacker: entity,
+                origin: None,
+                range: special_rules.range,
+                unparryable: special_rules.unparryable,
+            });
+        }
+    }
+}
@@ -0,0 +1,521 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{
+    input::{InputSet, PlayerInput},
+    player::{ClashCounter, Lineup, LineupEntry, Player, PlayerSlots},
+    replay::{MatchRecording, ReplayLine, Snapshot, REPLAY_VERSION},
+    GameState, MatchSettings, Points,
+};
+
+/// Streams the local match to spectators (`--broadcast <addr>`), or watches someone
+/// else's (`--spectate <addr>`). Both sides speak the replay format, one line at a time.
+pub struct SpectatorPlugin;
+
+impl Plugin for SpectatorPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<BroadcastSettings>()
+            .insert_resource(BroadcastSettings::default())
+            .insert_resource(AppliedFrames::default())
+            .add_systems(OnEnter(GameState::Spectate), reset_applied_frames)
+            .add_systems(
+                Update,
+                apply_spectated_frames
+                    .in_set(InputSet::Override)
+                    .run_if(in_state(GameState::Spectate)),
+            );
+
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_systems(Startup, net::start_from_args)
+            .add_systems(
+                Update,
+                (
+                    net::receive_spectator_feed.before(InputSet::Sample),
+                    net::stop_spectating.run_if(in_state(GameState::Spectate)),
+                ),
+            )
+            .add_systems(
+                PostUpdate,
+                net::broadcast_lines.after(crate::replay::record_frame),
+            )
+            .add_systems(OnExit(GameState::Match), net::flush_broadcast);
+    }
+}
+
+#[derive(Debug, Resource, Reflect)]
+#[reflect(Resource)]
+pub struct BroadcastSettings {
+    /// How far behind the live match spectators are kept, so the stream can't be
+    /// used to watch your opponent's inputs in real time. Once the match is over
+    /// the rest is sent straight away.
+    pub delay_secs: f32,
+}
+
+impl Default for BroadcastSettings {
+    fn default() -> Self {
+        BroadcastSettings { delay_secs: 3.0 }
+    }
+}
+
+/// How much of the [`MatchRecording`] has been shown so far.
+#[derive(Debug, Default, Resource)]
+struct AppliedFrames(usize);
+
+fn reset_applied_frames(mut applied: ResMut<AppliedFrames>) {
+    applied.0 = 0;
+}
+
+/// Where a spectated match's fighters come from: the replay header.
+fn lineup_from_recording(recording: &MatchRecording) -> Vec<LineupEntry> {
+    let Some(header) = &recording.header else {
+        return Vec::new();
+    };
+    header
+        .fighters
+        .iter()
+        .map(|fighter| LineupEntry {
+            player_number: fighter.player_number,
+            controls: None,
+            fighter: fighter.fighter,
+            color: Color::rgba_from_array(fighter.color),
+            palette: None,
+            profile: None,
+        })
+        .collect()
+}
+
+/// Snapshots are authoritative, so states are written directly rather than through
+/// the state machine.
+fn apply_snapshot(
+    snapshot: &Snapshot,
+    players: &mut Query<(&mut Player, &mut Transform, &mut PlayerInput)>,
+    slots: &PlayerSlots,
+    points: &mut Points,
+    clash_counter: &mut ClashCounter,
+) {
+    for player_snapshot in snapshot.players.iter() {
+        let Some(entity) = slots.get(player_snapshot.player_number) else {
+            continue;
+        };
+        let Ok((mut player, mut transform, _)) = players.get_mut(entity) else {
+            continue;
+        };
+        player.state = player_snapshot.state;
+        transform.translation.x = player_snapshot.position.0;
+        transform.translation.y = player_snapshot.position.1;
+        player
+            .attack_timer
+            .set_elapsed(Duration::from_secs_f32(player_snapshot.attack_elapsed));
+        player
+            .parry_timer
+            .set_elapsed(Duration::from_secs_f32(player_snapshot.parry_elapsed));
+        player
+            .clashing_timer
+            .set_elapsed(Duration::from_secs_f32(player_snapshot.clashing_elapsed));
+        player
+            .hitstun_timer
+            .set_elapsed(Duration::from_secs_f32(player_snapshot.hitstun_elapsed));
+        player
+            .special_timer
+            .set_elapsed(Duration::from_secs_f32(player_snapshot.special_elapsed));
+        player
+            .throw_timer
+            .set_elapsed(Duration::from_secs_f32(player_snapshot.throw_elapsed));
+        player
+            .dash_timer
+            .set_elapsed(Duration::from_secs_f32(player_snapshot.dash_elapsed));
+        player.velocity = player_snapshot.velocity;
+        player.health = player_snapshot.health;
+        player.meter = player_snapshot.meter;
+        player.combo_hits = player_snapshot.combo_hits;
+        player.combo_damage = player_snapshot.combo_damage;
+        player.last_hit_by = player_snapshot
+            .last_hit_by
+            .and_then(|player_number| slots.get(player_number));
+    }
+
+    points.scores = snapshot
+        .scores
+        .iter()
+        .filter_map(|(player_number, score)| Some((slots.get(*player_number)?, *score)))
+        .collect();
+    clash_counter.0 = snapshot.clash_counter;
+}
+
+/// Shows everything received since last frame: inputs go to the fighters (for the
+/// input history), and the newest snapshot sets where they are.
+fn apply_spectated_frames(
+    recording: Res<MatchRecording>,
+    slots: Res<PlayerSlots>,
+    mut players: Query<(&mut Player, &mut Transform, &mut PlayerInput)>,
+    mut points: ResMut<Points>,
+    mut clash_counter: ResMut<ClashCounter>,
+    mut applied: ResMut<AppliedFrames>,
+) {
+    let Some(new_frames) = recording.frames.get(applied.0..) else {
+        return;
+    };
+    let Some(latest) = new_frames.last() else {
+        return;
+    };
+    applied.0 = recording.frames.len();
+
+    for (player_number, sample) in latest.inputs.iter() {
+        let Some(entity) = slots.get(*player_number) else {
+            continue;
+        };
+        if let Ok((_, _, mut input)) = players.get_mut(entity) {
+            input.set_current(*sample);
+        }
+    }
+    if let Some(snapshot) = new_frames
+        .iter()
+        .rev()
+        .find_map(|frame| frame.snapshot.as_ref())
+    {
+        apply_snapshot(
+            snapshot,
+            &mut players,
+            &slots,
+            &mut points,
+            &mut clash_counter,
+        );
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod net {
+    use std::{
+        collections::VecDeque,
+        io::{self, BufRead, BufReader, Write},
+        net::{SocketAddr, TcpListener, TcpStream},
+        sync::{
+            mpsc::{self, Receiver},
+            Mutex,
+        },
+        thread,
+    };
+
+    use super::*;
+    use crate::replay::RecordedLine;
+
+    /// How much can queue up for one spectator before they are dropped.
+    const MAX_UNSENT_BYTES: usize = 4 * 1024 * 1024;
+
+    /// Sockets are non-blocking, so whatever a spectator's socket won't take yet
+    /// waits in `unsent` for the next frame.
+    struct Spectator {
+        addr: SocketAddr,
+        stream: TcpStream,
+        unsent: Vec<u8>,
+    }
+
+    impl Spectator {
+        /// Writes as much as the socket takes. Returns whether the spectator is still there.
+        fn flush(&mut self) -> bool {
+            while !self.unsent.is_empty() {
+                match self.stream.write(&self.unsent) {
+                    Ok(0) => return false,
+                    Ok(written) => {
+                        self.unsent.drain(..written);
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                    Err(_) => return false,
+                }
+            }
+            self.unsent.len() <= MAX_UNSENT_BYTES
+        }
+    }
+
+    /// Accepts spectators and holds each line back for `delay_secs`.
+    #[derive(Resource)]
+    pub struct Broadcaster {
+        listener: TcpListener,
+        spectators: Vec<Spectator>,
+        header: Option<String>,
+        /// Lines not sent yet, with when they can go out, in real seconds since startup.
+        pending: VecDeque<(f32, String)>,
+    }
+
+    impl Broadcaster {
+        fn bind(addr: &str) -> io::Result<Broadcaster> {
+            let listener = TcpListener::bind(addr)?;
+            listener.set_nonblocking(true)?;
+            Ok(Broadcaster {
+                listener,
+                spectators: Vec::new(),
+                header: None,
+                pending: VecDeque::new(),
+            })
+        }
+
+        fn send(&mut self, line: &str) {
+            for spectator in self.spectators.iter_mut() {
+                spectator.unsent.extend_from_slice(line.as_bytes());
+                spectator.unsent.push(b'\n');
+            }
+        }
+
+        /// Sends everything still held back.
+        fn send_pending(&mut self) {
+            while let Some((_, text)) = self.pending.pop_front() {
+                self.send(&text);
+            }
+        }
+
+        /// Spectators that fall too far behind or disconnect are dropped rather than
+        /// stalling the match.
+        fn flush(&mut self) {
+            self.spectators.retain_mut(|spectator| {
+                let connected = spectator.flush();
+                if !connected {
+                    println!("Spectator {} left", spectator.addr);
+                }
+                connected
+            });
+        }
+    }
+
+    /// Lines read off the host's stream by a background thread.
+    #[derive(Resource)]
+    pub struct SpectatorFeed(Mutex<Receiver<ReplayLine>>);
+
+    impl SpectatorFeed {
+        fn connect(addr: &str) -> io::Result<SpectatorFeed> {
+            let stream = TcpStream::connect(addr)?;
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || {
+                for line in BufReader::new(stream).lines() {
+                    let Ok(line) = line else {
+                        break;
+                    };
+                    match ReplayLine::from_line(&line) {
+                        Ok(line) => {
+                            if sender.send(line).is_err() {
+                                break;
+                            }
+                        }
+                        Err(err) => println!("Skipping unreadable spectator line: {}", err),
+                    }
+                }
+                println!("Spectator stream ended");
+            });
+            Ok(SpectatorFeed(Mutex::new(receiver)))
+        }
+    }
+
+    pub fn stop_spectating(
+        mut commands: Commands,
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+        mut next_state: ResMut<NextState<GameState>>,
+    ) {
+        if keyboard_input.just_pressed(KeyCode::Escape) {
+            commands.remove_resource::<SpectatorFeed>();
+            next_state.set(GameState::Lobby);
+        }
+    }
+
+    pub fn start_from_args(mut commands: Commands) {
+        let args: Vec<String> = std::env::args().collect();
+        let value_of = |flag: &str| {
+            args.iter()
+                .position(|arg| arg == flag)
+                .and_then(|index| args.get(index + 1))
+        };
+
+        if let Some(addr) = value_of("--broadcast") {
+            match Broadcaster::bind(addr) {
+                Ok(broadcaster) => {
+                    println!("Broadcasting matches on {}", addr);
+                    commands.insert_resource(broadcaster);
+                }
+                Err(err) => println!("Couldn't broadcast on {}: {}", addr, err),
+            }
+        }
+        if let Some(addr) = value_of("--spectate") {
+            match SpectatorFeed::connect(addr) {
+                Ok(feed) => {
+                    println!("Spectating {}", addr);
+                    commands.insert_resource(feed);
+                }
+                Err(err) => println!("Couldn't spectate {}: {}", addr, err),
+            }
+        }
+    }
+
+    pub fn broadcast_lines(
+        broadcaster: Option<ResMut<Broadcaster>>,
+        settings: Res<BroadcastSettings>,
+        time: Res<Time<Real>>,
+        mut ev_recorded: EventReader<RecordedLine>,
+    ) {
+        let Some(mut broadcaster) = broadcaster else {
+            ev_recorded.clear();
+            return;
+        };
+
+        while let Ok((stream, addr)) = broadcaster.listener.accept() {
+            if stream.set_nonblocking(true).is_err() || stream.set_nodelay(true).is_err() {
+                continue;
+            }
+            println!("Spectator joined from {}", addr);
+            let mut unsent = Vec::new();
+            if let Some(header) = &broadcaster.header {
+                unsent.extend_from_slice(header.as_bytes());
+                unsent.push(b'\n');
+            }
+            broadcaster.spectators.push(Spectator {
+                addr,
+                stream,
+                unsent,
+            });
+        }
+
+        let now = time.elapsed_seconds();
+        for RecordedLine(line) in ev_recorded.read() {
+            let text = line.to_line();
+            match line {
+                ReplayLine::Header(_) => {
+                    // A new match: the end of the old one goes out first.
+                    broadcaster.send_pending();
+                    broadcaster.send(&text);
+                    broadcaster.header = Some(text);
+                }
+                ReplayLine::Frame(_) => {
+                    broadcaster
+                        .pending
+                        .push_back((now + settings.delay_secs, text));
+                }
+            }
+        }
+
+        while let Some((send_at, _)) = broadcaster.pending.front() {
+            if *send_at > now {
+                break;
+            }
+            let (_, text) = broadcaster.pending.pop_front().unwrap();
+            broadcaster.send(&text);
+        }
+        broadcaster.flush();
+    }
+
+    /// Nothing is left to hide once the match is over.
+    pub fn flush_broadcast(broadcaster: Option<ResMut<Broadcaster>>) {
+        if let Some(mut broadcaster) = broadcaster {
+            broadcaster.send_pending();
+            broadcaster.flush();
+        }
+    }
+
+    /// Fills the recording from the host's stream. A header starts a new spectated
+    /// match: the fighters are respawned by bouncing through the lobby.
+    pub fn receive_spectator_feed(
+        feed: Option<Res<SpectatorFeed>>,
+        state: Res<State<GameState>>,
+        mut next_state: ResMut<NextState<GameState>>,
+        mut recording: ResMut<MatchRecording>,
+        mut lineup: ResMut<Lineup>,
+        mut match_settings: ResMut<MatchSettings>,
+    ) {
+        let Some(feed) = feed else {
+            return;
+        };
+        let receiver = feed.0.lock().unwrap();
+
+        while let Ok(line) = receiver.try_recv() {
+            match line {
+                ReplayLine::Header(header) => {
+                    if header.version != REPLAY_VERSION {
+                        println!(
+                            "Can't spectate: stream is replay version {}, this build reads {}",
+                            header.version, REPLAY_VERSION
+                        );
+                        continue;
+                    }
+                    match_settings.mode = header.mode;
+                    match_settings.clash_preset = header.clash_preset;
+                    recording.start(header);
+                    lineup.0 = lineup_from_recording(&recording);
+                    if *state.get() == GameState::Spectate {
+                        next_state.set(GameState::Lobby);
+                    }
+                }
+                ReplayLine::Frame(frame) => {
+                    if recording.header.is_some() {
+                        recording.frames.push(frame);
+                    }
+                }
+            }
+        }
+
+        if *state.get() == GameState::Lobby && recording.header.is_some() {
+            next_state.set(GameState::Spectate);
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::time::Duration;
+
+        use bevy::ecs::system::RunSystemOnce;
+
+        use super::*;
+        use crate::replay::{ReplayFrame, ReplayHeader};
+
+        fn read_line(reader: &mut BufReader<TcpStream>) -> ReplayLine {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            ReplayLine::from_line(line.trim_end()).unwrap()
+        }
+
+        #[test]
+        fn held_back_frames_go_out_when_the_match_ends() {
+            let mut app = App::new();
+            let broadcaster = Broadcaster::bind("127.0.0.1:0").unwrap();
+            let addr = broadcaster.listener.local_addr().unwrap();
+            app.add_event::<RecordedLine>()
+                .insert_resource(broadcaster)
+                .insert_resource(BroadcastSettings::default())
+                .insert_resource(Time::<Real>::default())
+                .add_systems(Update, broadcast_lines);
+
+            let client = TcpStream::connect(addr).unwrap();
+            client
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut reader = BufReader::new(client);
+            while app.world.resource::<Broadcaster>().spectators.is_empty() {
+                app.update();
+            }
+
+            app.world
+                .send_event(RecordedLine(ReplayLine::Header(ReplayHeader {
+                    version: REPLAY_VERSION,
+                    mode: default(),
+                    clash_preset: default(),
+                    fighters: Vec::new(),
+                })));
+            for frame in 0..3 {
+                app.world
+                    .send_event(RecordedLine(ReplayLine::Frame(ReplayFrame {
+                        frame,
+                        inputs: Vec::new(),
+                        snapshot: None,
+                    })));
+            }
+            app.update();
+            assert!(matches!(read_line(&mut reader), ReplayLine::Header(_)));
+            assert_eq!(app.world.resource::<Broadcaster>().pending.len(), 3);
+
+            app.world.run_system_once(flush_broadcast);
+            for expected in 0..3 {
+                match read_line(&mut reader) {
+                    ReplayLine::Frame(frame) => assert_eq!(frame.frame, expected),
+                    ReplayLine::Header(_) => panic!("expected frame {}", expected),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use bevy::{
+    prelude::*,
+    sprite::{MaterialMesh2dBundle, Mesh2dHandle},
+};
+
+use crate::{
+    player::{Player, PlayerState},
+    GameState,
+};
+
+/// Shows each fighter's state with a tag and an outline as well as its color, so it
+/// can be read without telling hues apart.
+pub struct StateCuePlugin;
+
+impl Plugin for StateCuePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            update_state_cues
+                .run_if(in_state(GameState::Match).or_else(in_state(GameState::Spectate))),
+        );
+    }
+}
+
+/// How far the outline sticks out past the fighter on each side.
+const OUTLINE_WIDTH: f32 = 12.0;
+/// Times per second the outline flashes while a fighter can still parry.
+const PARRY_FLASH_RATE: f32 = 8.0;
+
+/// Sits behind the fighter and is only shown in states worth reacting to.
+#[derive(Component)]
+struct StateOutline;
+
+/// "P1" and a mark for the current state, above the fighter.
+#[derive(Component)]
+struct StateTag;
+
+/// Spawned as children of a fighter `size` units wide.
+pub fn spawn_state_cues(
+    parent: &mut ChildBuilder,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    size: f32,
+    player_number: u8,
+) {
+    let outline_size = size + OUTLINE_WIDTH * 2.0;
+    parent
+        .spawn(MaterialMesh2dBundle {
+            mesh: Mesh2dHandle(meshes.add(Rectangle::new(outline_size, outline_size))),
+            material: materials.add(Color::WHITE),
+            transform: Transform::from_xyz(0.0, 0.0, -0.1),
+            visibility: Visibility::Hidden,
+            ..default()
+        })
+        .insert(StateOutline);
+    parent
+        .spawn(Text2dBundle {
+            text: Text::from_sections([
+                TextSection::new(
+                    format!("P{}", player_number),
+                    TextStyle {
+                        font_size: 60.0,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                ),
+                TextSection::new(
+                    String::new(),
+                    TextStyle {
+                        font_size: 60.0,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                ),
+            ]),
+            transform: Transform::from_xyz(0.0, size / 2.0 + OUTLINE_WIDTH + 40.0, 0.1),
+            ..default()
+        })
+        .insert(StateTag);
+}
+
+fn state_mark(player: &Player) -> &'static str {
+    match player.state {
+        PlayerState::Dead => " X",
+        PlayerState::TakingDamage => " !!",
+        PlayerState::Clashing => " <>",
+        PlayerState::Hitstun => " ~",
+        PlayerState::Reversal => " ^",
+        PlayerState::LongStrike => " >>",
+        PlayerState::Firing => " o",
+        PlayerState::Throwing => " ><",
+        PlayerState::Thrown => " ?!",
+        PlayerState::Teching => " =",
+        PlayerState::Dashing => " >",
+        PlayerState::Backdashing => " <",
+        _ if !player.attack_timer.finished() => " ...",
+        _ => "",
+    }
+}
+
+fn update_state_cues(
+    time: Res<Time>,
+    players: Query<(&Player, &Children)>,
+    mut outlines: Query<&mut Visibility, With<StateOutline>>,
+    mut tags: Query<&mut Text, With<StateTag>>,
+) {
+    let flash_on = (time.elapsed_seconds() * PARRY_FLASH_RATE).fract() < 0.5;
+    for (player, children) in players.iter() {
+        let outlined = match player.state {
+            _ if player.invulnerable() => true,
+            PlayerState::Clashing | PlayerState::Teching => true,
+            PlayerState::TakingDamage | PlayerState::Thrown => flash_on,
+            _ => false,
+        };
+        let visibility = if outlined {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        let mut outlines = outlines.iter_many_mut(children);
+        while let Some(mut outline) = outlines.fetch_next() {
+            if *outline != visibility {
+                *outline = visibility;
+            }
+        }
+
+        let mark = state_mark(player);
+        let mut tags = tags.iter_many_mut(children);
+        while let Some(mut tag) = tags.fetch_next() {
+            if tag.sections[1].value != mark {
+                tag.sections[1].value = mark.to_string();
+            }
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::player::{Player, PlayerState, PlayerStateChangeEvent};
+
+impl PlayerState {
+    /// The transitions gameplay is allowed to make. Resetting a round goes through
+    /// [`PlayerStateMachine::reset`] instead, which can leave any state.
+    pub fn can_transition_to(self, to: PlayerState) -> bool {
+        use PlayerState::*;
+        matches!(
+            (self, to),
+            (
+                Alive,
+                TakingDamage
+                    | Wiff
+                    | Clashing
+                    | Hitstun
+                    | Dead
+                    | Reversal
+                    | LongStrike
+                    | Firing
+                    | Throwing
+                    | Thrown
+                    | Dashing
+                    | Backdashing
+            ) | (TakingDamage, Dead | Clashing | Hitstun | Reversal)
+                | (
+                    Wiff,
+                    Alive | TakingDamage | Clashing | Hitstun | Dead | Thrown
+                )
+                | (Clashing, Alive)
+                | (Hitstun, Alive | Dead | Reversal)
+                | (Reversal, Alive)
+                | (
+                    LongStrike | Firing,
+                    Alive | TakingDamage | Hitstun | Dead | Thrown
+                )
+                | (Throwing, Alive | Teching)
+                | (Thrown, Teching | Hitstun | Dead)
+                | (Teching, Alive)
+                | (
+                    Dashing | Backdashing,
+                    Alive | TakingDamage | Hitstun | Dead | Thrown
+                )
+        )
+    }
+}
+
+/// Conditions on the fighter itself that have to hold for a legal transition to go through.
+fn guard(player: &Player, to: PlayerState) -> bool {
+    match (player.state, to) {
+        (PlayerState::TakingDamage, PlayerState::Clashing) => !player.parry_timer.finished(),
+        (PlayerState::Hitstun, PlayerState::Alive) => player.hitstun_timer.finished(),
+        (
+            PlayerState::Reversal | PlayerState::LongStrike | PlayerState::Firing,
+            PlayerState::Alive,
+        ) => player.special_timer.finished(),
+        (PlayerState::Throwing | PlayerState::Teching, PlayerState::Alive) => {
+            player.throw_timer.finished()
+        }
+        (PlayerState::Thrown, PlayerState::Teching) => !player.parry_timer.finished(),
+        (PlayerState::Dashing | PlayerState::Backdashing, PlayerState::Alive) => {
+            player.dash_timer.finished()
+        }
+        (_, PlayerState::Dead) => player.health <= 0.0,
+        (PlayerState::Wiff, PlayerState::Alive) => player.attack_timer.finished(),
+        (PlayerState::Clashing, PlayerState::Alive) => player.clashing_timer.finished(),
+        _ => true,
+    }
+}
+
+/// Starts the timers a state is measured by.
+fn on_enter(player: &mut Player, to: PlayerState) {
+    match to {
+        PlayerState::TakingDamage | PlayerState::Thrown => player.parry_timer.reset(),
+        PlayerState::Clashing => player.clashing_timer.reset(),
+        _ => {}
+    }
+    // Any combo is over once the fighter is back on their feet.
+    if to == PlayerState::Alive {
+        player.combo_hits = 0;
+        player.combo_damage = 0.0;
+    }
+}
+
+/// The only way `Player::state` should change. Every change runs the enter hook and
+/// sends a [`PlayerStateChangeEvent`].
+#[derive(SystemParam)]
+pub struct PlayerStateMachine<'w> {
+    ev_player_state_change: EventWriter<'w, PlayerStateChangeEvent>,
+}
+
+impl PlayerStateMachine<'_> {
+    /// Moves `player` to `to` if the transition is legal and its guard passes. Staying in
+    /// the same state is a no-op that counts as success.
+    pub fn transition(&mut self, entity: Entity, player: &mut Player, to: PlayerState) -> bool {
+        if player.state == to {
+            return true;
+        }
+        if !player.state.can_transition_to(to) {
+            #[cfg(debug_assertions)]
+            warn!(
+                "Illegal state transition for player {}: {:?} -> {:?}",
+                player.player_number, player.state, to
+            );
+            return false;
+        }
+        if !guard(player, to) {
+            return false;
+        }
+        self.apply(entity, player, to);
+        true
+    }
+
+    /// Whether [`Self::transition`] would succeed, without making it. For moves that
+    /// change two fighters at once, so neither changes unless both can.
+    pub fn can_transition(&self, player: &Player, to: PlayerState) -> bool {
+        player.state == to || (player.state.can_transition_to(to) && guard(player, to))
+    }
+
+    /// Puts `player` back to `Alive` from any state, always sending the event.
+    pub fn reset(&mut self, entity: Entity, player: &mut Player) {
+        self.apply(entity, player, PlayerState::Alive);
+    }
+
+    fn apply(&mut self, entity: Entity, player: &mut Player, to: PlayerState) {
+        player.state = to;
+        on_enter(player, to);
+        self.ev_player_state_change
+            .send(PlayerStateChangeEvent(entity, to));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use PlayerState::*;
+
+    const ALL: [PlayerState; 14] = [
+        Alive,
+        Dead,
+        TakingDamage,
+        Clashing,
+        Wiff,
+        Hitstun,
+        Reversal,
+        LongStrike,
+        Firing,
+        Throwing,
+        Thrown,
+        Teching,
+        Dashing,
+        Backdashing,
+    ];
+
+    fn exits(from: PlayerState) -> Vec<PlayerState> {
+        ALL.into_iter()
+            .filter(|to| from.can_transition_to(*to))
+            .collect()
+    }
+
+    fn finished(seconds: f32) -> Timer {
+        let mut timer = Timer::from_seconds(seconds, TimerMode::Once);
+        timer.tick(Duration::from_secs_f32(seconds));
+        timer
+    }
+
+    #[test]
+    fn dead_is_final() {
+        assert!(exits(Dead).is_empty());
+    }
+
+    #[test]
+    fn a_parry_window_can_only_end_in_a_parry_a_hit_or_a_reversal() {
+        assert_eq!(exits(TakingDamage), vec![Dead, Clashing, Hitstun, Reversal]);
+    }
+
+    #[test]
+    fn recoveries_only_go_back_to_alive() {
+        for from in [Clashing, Reversal, Teching] {
+            assert_eq!(exits(from), vec![Alive], "{:?}", from);
+        }
+    }
+
+    #[test]
+    fn a_grab_ends_in_a_tech_or_the_throw() {
+        assert_eq!(exits(Thrown), vec![Dead, Hitstun, Teching]);
+        assert_eq!(exits(Throwing), vec![Alive, Teching]);
+    }
+
+    #[test]
+    fn parrying_needs_the_window_open() {
+        let mut player = Player {
+            state: TakingDamage,
+            ..default()
+        };
+        assert!(guard(&player, Clashing));
+        player.parry_timer = finished(0.064);
+        assert!(!guard(&player, Clashing));
+    }
+
+    #[test]
+    fn entering_clashing_restarts_the_clash_timer() {
+        let mut player = Player {
+            clashing_timer: finished(1.0),
+            ..default()
+        };
+        on_enter(&mut player, Clashing);
+        player.state = Clashing;
+        assert!(!guard(&player, Alive));
+        player.clashing_timer.tick(Duration::from_secs(1));
+        assert!(guard(&player, Alive));
+    }
+
+    #[test]
+    fn only_an_empty_health_bar_is_a_knockout() {
+        let mut player = Player::default();
+        assert!(!guard(&player, Dead));
+        player.health = 0.0;
+        assert!(guard(&player, Dead));
+    }
+
+    #[test]
+    fn timed_states_wait_for_their_timer() {
+        let mut player = Player {
+            state: Hitstun,
+            hitstun_timer: Timer::from_seconds(1.0, TimerMode::Once),
+            ..default()
+        };
+        assert!(!guard(&player, Alive));
+        player.hitstun_timer = finished(1.0);
+        assert!(guard(&player, Alive));
+    }
+}
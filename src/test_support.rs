@@ -0,0 +1,90 @@
+//! Apps and helpers shared by the hit tests, with only the systems a test needs and a
+//! clock it moves by hand.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{
+    clash::ClashRules,
+    combo::{ComboEnded, ComboHit, ComboRules},
+    player::{
+        check_attack_hit, frames_to_secs, player_timer_update, AttackEvent, ClashCounter,
+        ClashEvent, Player, PlayerStateChangeEvent,
+    },
+    projectile::{
+        cancel_projectiles, hit_with_projectiles, move_projectiles, reflect_projectiles,
+        release_projectiles, ProjectileRules,
+    },
+    MatchSettings,
+};
+
+/// Just the hit and timer systems.
+pub(crate) fn hit_app() -> App {
+    let mut app = App::new();
+    app.add_event::<AttackEvent>()
+        .add_event::<PlayerStateChangeEvent>()
+        .add_event::<ClashEvent>()
+        .add_event::<ComboHit>()
+        .add_event::<ComboEnded>()
+        .insert_resource(Time::<()>::default())
+        .insert_resource(ClashCounter::default())
+        .insert_resource(ClashRules::default())
+        .insert_resource(ComboRules::default())
+        .insert_resource(MatchSettings::default())
+        .add_systems(Update, (check_attack_hit, player_timer_update).chain());
+    app
+}
+
+/// [`hit_app`] with projectiles flying, ordered around the hit check as in the game.
+pub(crate) fn projectile_app() -> App {
+    let mut app = hit_app();
+    app.insert_resource(ProjectileRules::default()).add_systems(
+        Update,
+        (
+            (
+                reflect_projectiles,
+                move_projectiles,
+                cancel_projectiles,
+                hit_with_projectiles,
+            )
+                .chain()
+                .before(check_attack_hit),
+            release_projectiles
+                .after(check_attack_hit)
+                .before(player_timer_update),
+        ),
+    );
+    app
+}
+
+pub(crate) fn spawn_fighter(app: &mut App, team: u8, x: f32) -> Entity {
+    app.world
+        .spawn((
+            Player { team, ..default() },
+            Transform::from_xyz(x, 0.0, 0.0),
+        ))
+        .id()
+}
+
+pub(crate) fn attack(app: &mut App, attacker: Entity, range: f32) {
+    app.world.send_event(AttackEvent {
+        attacker,
+        origin: None,
+        range,
+        unparryable: false,
+    });
+}
+
+pub(crate) fn step(app: &mut App, frames: u32) {
+    for _ in 0..frames {
+        app.world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(frames_to_secs(1)));
+        app.update();
+    }
+}
+
+pub(crate) fn player(app: &App, entity: Entity) -> &Player {
+    app.world.get::<Player>(entity).unwrap()
+}
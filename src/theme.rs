@@ -0,0 +1,204 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use serde::{Deserialize, Serialize};
+
+use crate::{player::Player, save::SaveData};
+
+/// Font, sizes, colors and UI scale for every screen. Text is spawned with the default
+/// font at its base size and the theme is applied on top, so screens don't need to
+/// know about it.
+pub struct ThemePlugin;
+
+impl Plugin for ThemePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(UiTheme::default()).add_systems(
+            Update,
+            (
+                update_theme,
+                scale_ui,
+                apply_theme_to_text,
+                apply_theme_to_panels,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// The UI was laid out for a window of this size; `UiScale` is 1.0 there.
+const REFERENCE_SIZE: Vec2 = Vec2::new(1280.0, 720.0);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Reflect, Serialize, Deserialize)]
+pub enum ThemePreset {
+    #[default]
+    Standard,
+    /// Solid black panels and vivid player colors.
+    HighContrast,
+    /// Everything 1.5 times bigger.
+    LargeText,
+}
+
+impl ThemePreset {
+    pub fn next(self) -> Self {
+        match self {
+            ThemePreset::Standard => ThemePreset::HighContrast,
+            ThemePreset::HighContrast => ThemePreset::LargeText,
+            ThemePreset::LargeText => ThemePreset::Standard,
+        }
+    }
+}
+
+#[derive(Debug, Resource)]
+pub struct UiTheme {
+    /// The default handle is Bevy's built-in font.
+    pub font: Handle<Font>,
+    /// Multiplies every font size.
+    pub text_scale: f32,
+    pub high_contrast: bool,
+}
+
+impl Default for UiTheme {
+    fn default() -> Self {
+        UiTheme {
+            font: Handle::default(),
+            text_scale: 1.0,
+            high_contrast: false,
+        }
+    }
+}
+
+impl UiTheme {
+    /// The color a fighter is shown with in the UI, matching its body.
+    pub fn player_color(&self, player: &Player) -> Color {
+        if !self.high_contrast {
+            return player.color;
+        }
+        let [hue, _, _, alpha] = player.color.as_hsla_f32();
+        Color::hsla(hue, 1.0, 0.6, alpha)
+    }
+}
+
+/// Each section's font size before the theme scaled it.
+#[derive(Component)]
+struct BaseFontSizes(Vec<f32>);
+
+#[derive(Component)]
+struct BaseBackground(Color);
+
+fn update_theme(
+    save: Res<SaveData>,
+    asset_server: Res<AssetServer>,
+    mut theme: ResMut<UiTheme>,
+    mut loaded_font: Local<Option<String>>,
+) {
+    if !save.is_changed() {
+        return;
+    }
+    let preset = save.video.theme;
+    let text_scale = match preset {
+        ThemePreset::LargeText => 1.5,
+        _ => 1.0,
+    };
+    let high_contrast = preset == ThemePreset::HighContrast;
+    if theme.text_scale != text_scale || theme.high_contrast != high_contrast {
+        theme.text_scale = text_scale;
+        theme.high_contrast = high_contrast;
+    }
+
+    if *loaded_font != save.video.font {
+        *loaded_font = save.video.font.clone();
+        theme.font = match &save.video.font {
+            Some(path) => asset_server.load(path.clone()),
+            None => Handle::default(),
+        };
+    }
+}
+
+/// Scales the UI with the window, clamped to the window's own resize limits, times
+/// the player's own UI scale option.
+fn scale_ui(
+    save: Res<SaveData>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut ui_scale: ResMut<UiScale>,
+) {
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let limits = window.resize_constraints.check_constraints();
+    let size = Vec2::new(
+        window.width().clamp(limits.min_width, limits.max_width),
+        window.height().clamp(limits.min_height, limits.max_height),
+    );
+    let fit = (size / REFERENCE_SIZE).min_element();
+    let scale = fit * save.video.ui_scale;
+    if ui_scale.0 != scale {
+        ui_scale.0 = scale;
+    }
+}
+
+fn themed_section(section: &mut TextSection, base_size: f32, theme: &UiTheme) {
+    section.style.font_size = base_size * theme.text_scale;
+    section.style.font = theme.font.clone();
+}
+
+fn apply_theme_to_text(
+    mut commands: Commands,
+    theme: Res<UiTheme>,
+    mut new_texts: Query<(Entity, &mut Text), Without<BaseFontSizes>>,
+    mut texts: Query<(&mut BaseFontSizes, &mut Text)>,
+) {
+    for (entity, mut text) in new_texts.iter_mut() {
+        let base: Vec<_> = text
+            .sections
+            .iter()
+            .map(|section| section.style.font_size)
+            .collect();
+        for (section, size) in text.sections.iter_mut().zip(base.iter()) {
+            themed_section(section, *size, &theme);
+        }
+        commands.entity(entity).insert(BaseFontSizes(base));
+    }
+
+    if !theme.is_changed() {
+        return;
+    }
+    for (mut base, mut text) in texts.iter_mut() {
+        // Sections added since the text was first seen are taken as they are now.
+        for section in text.sections.iter().skip(base.0.len()) {
+            base.0.push(section.style.font_size);
+        }
+        for (section, size) in text.sections.iter_mut().zip(base.0.iter()) {
+            themed_section(section, *size, &theme);
+        }
+    }
+}
+
+fn panel_color(base: Color, theme: &UiTheme) -> Color {
+    let dark = base.a() > 0.0 && base.l() < 0.2;
+    if theme.high_contrast && dark {
+        Color::BLACK
+    } else {
+        base
+    }
+}
+
+/// High contrast turns the dark, see-through panels behind menus solid black.
+fn apply_theme_to_panels(
+    mut commands: Commands,
+    theme: Res<UiTheme>,
+    mut new_panels: Query<(Entity, &mut BackgroundColor), Without<BaseBackground>>,
+    mut panels: Query<(&BaseBackground, &mut BackgroundColor)>,
+) {
+    for (entity, mut background) in new_panels.iter_mut() {
+        commands.entity(entity).insert(BaseBackground(background.0));
+        background.0 = panel_color(background.0, &theme);
+    }
+
+    if !theme.is_changed() {
+        return;
+    }
+    for (base, mut background) in panels.iter_mut() {
+        let color = panel_color(base.0, &theme);
+        if background.0 != color {
+            background.0 = color;
+        }
+    }
+}
@@ -0,0 +1,405 @@
+use std::time::Duration;
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_tweening::{lens::TransformPositionLens, Animator, EaseFunction, Tween};
+
+use crate::{
+    clash::ClashRules,
+    combo::ComboEnded,
+    fighter::CombatStats,
+    input::{InputSet, PlayerInput},
+    pause::not_paused,
+    player::{combo_ended, deal_damage, frames_to_secs, ClashCounter, Player, PlayerState},
+    state_machine::PlayerStateMachine,
+    GameState, MatchSettings,
+};
+
+/// The throw button grabs a fighter standing right next to you. A grab can't be
+/// parried; instead the grabbed fighter gets the parry window, on the same
+/// `parry_timer` and with the same length from [`ClashRules::parry_window`], to press
+/// throw themselves and tech it. A tech pushes both fighters apart, otherwise the
+/// throw lands once the window is over.
+pub struct ThrowPlugin;
+
+impl Plugin for ThrowPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ThrowEvent>()
+            .add_event::<TechEvent>()
+            .register_type::<ThrowRules>()
+            .insert_resource(ThrowRules::default())
+            .add_systems(
+                Update,
+                (attempt_throws, tech_throws, land_throws)
+                    .chain()
+                    .after(InputSet::Override)
+                    .run_if(in_state(GameState::Match))
+                    .run_if(not_paused),
+            )
+            .add_systems(Update, log_throws);
+    }
+}
+
+/// Sent when a fighter is grabbed.
+#[derive(Event, Debug)]
+pub struct ThrowEvent {
+    pub thrower: Entity,
+    pub defender: Entity,
+}
+
+/// Sent when a grabbed fighter breaks free.
+#[derive(Event, Debug)]
+pub struct TechEvent {
+    pub thrower: Entity,
+    pub defender: Entity,
+}
+
+#[derive(Debug, Resource, Clone, Reflect)]
+#[reflect(Resource)]
+pub struct ThrowRules {
+    /// How close a fighter has to be to get grabbed, edge to edge, so it is the same
+    /// gap whatever the fighters' sizes.
+    pub range: f32,
+    /// How long the thrower is busy after a grab, tech window included.
+    pub throw_frames: u32,
+    pub damage: f32,
+    pub hitstun_frames: u32,
+    /// How long both fighters are out after a tech.
+    pub tech_recovery_frames: u32,
+    /// How far each fighter is pushed by a tech.
+    pub tech_pushback: f32,
+}
+
+impl Default for ThrowRules {
+    fn default() -> Self {
+        ThrowRules {
+            range: 20.0,
+            throw_frames: 40,
+            damage: 30.0,
+            hitstun_frames: 60,
+            tech_recovery_frames: 20,
+            tech_pushback: 150.0,
+        }
+    }
+}
+
+/// Blocking is parrying here, so a throw grabs anyone who could parry, or is open anyway.
+fn throwable(player: &Player) -> bool {
+    matches!(
+        player.state,
+        PlayerState::Alive
+            | PlayerState::Wiff
+            | PlayerState::LongStrike
+            | PlayerState::Firing
+            | PlayerState::Dashing
+            | PlayerState::Backdashing
+    ) && !player.invulnerable()
+}
+
+fn set_timer(timer: &mut Timer, frames: u32) {
+    timer.set_duration(Duration::from_secs_f32(frames_to_secs(frames)));
+    timer.reset();
+}
+
+/// A throw shares the attack cooldown, and misses into the same `Wiff` as an attack.
+#[allow(clippy::too_many_arguments)]
+fn attempt_throws(
+    mut players: Query<(Entity, &mut Player, &PlayerInput, &Transform)>,
+    mut state_machine: PlayerStateMachine,
+    mut ev_throw: EventWriter<ThrowEvent>,
+    rules: Res<ThrowRules>,
+    clash_rules: Res<ClashRules>,
+    clash_counter: Res<ClashCounter>,
+    match_settings: Res<MatchSettings>,
+) {
+    let throwers: Vec<Entity> = players
+        .iter()
+        .filter(|(_, player, input, _)| {
+            input.throw_just_pressed()
+                && player.state == PlayerState::Alive
+                && player.attack_timer.finished()
+        })
+        .map(|(entity, ..)| entity)
+        .collect();
+
+    for thrower in throwers {
+        // Someone else's throw may have grabbed this fighter first.
+        let Ok((_, player, _, transform)) = players.get(thrower) else {
+            continue;
+        };
+        if player.state != PlayerState::Alive {
+            continue;
+        }
+        let (team, x) = (player.team, transform.translation.x);
+        let half_width = player.definition().size / 2.0;
+        let target = players
+            .iter()
+            .filter(|(entity, other, _, other_transform)| {
+                let gap = (other_transform.translation.x - x).abs()
+                    - half_width
+                    - other.definition().size / 2.0;
+                *entity != thrower
+                    && throwable(other)
+                    && (match_settings.friendly_fire || other.team != team)
+                    && gap < rules.range
+            })
+            .min_by(|(.., a), (.., b)| {
+                (a.translation.x - x)
+                    .abs()
+                    .total_cmp(&(b.translation.x - x).abs())
+            })
+            .map(|(entity, ..)| entity);
+        // Nothing changes unless both fighters can go into the throw.
+        let target = target.filter(|target| {
+            players
+                .get_many([thrower, *target])
+                .is_ok_and(|[(_, player, ..), (_, defender, ..)]| {
+                    state_machine.can_transition(defender, PlayerState::Thrown)
+                        && state_machine.can_transition(player, PlayerState::Throwing)
+                })
+        });
+
+        let Some(target) = target else {
+            let Ok((_, mut player, ..)) = players.get_mut(thrower) else {
+                continue;
+            };
+            player.attack_timer.reset();
+            println!("Player {:?} missed a throw", player.player_number);
+            state_machine.transition(thrower, &mut player, PlayerState::Wiff);
+            continue;
+        };
+
+        let Ok([(_, mut player, ..), (_, mut defender, ..)]) =
+            players.get_many_mut([thrower, target])
+        else {
+            continue;
+        };
+        player.attack_timer.reset();
+        defender.parry_timer.set_duration(Duration::from_secs_f32(
+            clash_rules.parry_window.at(clash_counter.0),
+        ));
+        defender.last_hit_by = Some(thrower);
+        state_machine.transition(target, &mut defender, PlayerState::Thrown);
+        set_timer(&mut player.throw_timer, rules.throw_frames);
+        state_machine.transition(thrower, &mut player, PlayerState::Throwing);
+        ev_throw.send(ThrowEvent {
+            thrower,
+            defender: target,
+        });
+    }
+}
+
+fn tech_throws(
+    mut commands: Commands,
+    mut players: Query<(Entity, &mut Player, &PlayerInput, &Transform)>,
+    mut state_machine: PlayerStateMachine,
+    mut ev_tech: EventWriter<TechEvent>,
+    rules: Res<ThrowRules>,
+) {
+    let techs: Vec<(Entity, Entity)> = players
+        .iter()
+        .filter(|(_, player, input, _)| {
+            player.state == PlayerState::Thrown && input.throw_just_pressed()
+        })
+        .filter_map(|(entity, player, ..)| Some((player.last_hit_by?, entity)))
+        .collect();
+
+    for (thrower, defender) in techs {
+        let Ok(
+            [(_, mut thrower_player, _, thrower_transform), (_, mut defender_player, _, defender_transform)],
+        ) = players.get_many_mut([thrower, defender])
+        else {
+            continue;
+        };
+        if thrower_player.state != PlayerState::Throwing
+            || !state_machine.transition(defender, &mut defender_player, PlayerState::Teching)
+        {
+            continue;
+        }
+        state_machine.transition(thrower, &mut thrower_player, PlayerState::Teching);
+        set_timer(&mut thrower_player.throw_timer, rules.tech_recovery_frames);
+        set_timer(&mut defender_player.throw_timer, rules.tech_recovery_frames);
+
+        let direction = if thrower_transform.translation.x < defender_transform.translation.x {
+            -1.0
+        } else {
+            1.0
+        };
+        let duration = Duration::from_secs_f32(frames_to_secs(rules.tech_recovery_frames));
+        for (entity, start, offset) in [
+            (thrower, thrower_transform.translation, direction),
+            (defender, defender_transform.translation, -direction),
+        ] {
+            commands.entity(entity).insert(Animator::new(Tween::new(
+                EaseFunction::QuadraticOut,
+                duration,
+                TransformPositionLens {
+                    start,
+                    end: start + Vec3::X * offset * rules.tech_pushback,
+                },
+            )));
+        }
+        ev_tech.send(TechEvent { thrower, defender });
+    }
+}
+
+/// A grab nobody teched deals its damage once the tech window is over.
+fn land_throws(
+    mut players: Query<(Entity, &mut Player)>,
+    mut state_machine: PlayerStateMachine,
+    mut ev_combo_ended: EventWriter<ComboEnded>,
+    rules: Res<ThrowRules>,
+) {
+    let combat_stats: HashMap<Entity, &CombatStats> = players
+        .iter()
+        .map(|(entity, player)| (entity, &player.definition().combat))
+        .collect();
+    for (entity, mut player) in players.iter_mut() {
+        if player.state != PlayerState::Thrown || !player.parry_timer.finished() {
+            continue;
+        }
+        let thrower = player
+            .last_hit_by
+            .and_then(|thrower| combat_stats.get(&thrower).copied())
+            .unwrap_or(&CombatStats::UNSCALED);
+        let hitstun = Duration::from_secs_f32(frames_to_secs(rules.hitstun_frames))
+            .mul_f32(thrower.hitstun_scale);
+        deal_damage(
+            entity,
+            &mut player,
+            rules.damage * thrower.damage_scale,
+            hitstun,
+            &mut state_machine,
+        );
+        if player.state == PlayerState::Dead {
+            ev_combo_ended.send(combo_ended(entity, &player));
+        }
+    }
+}
+
+fn log_throws(
+    mut ev_throw: EventReader<ThrowEvent>,
+    mut ev_tech: EventReader<TechEvent>,
+    players: Query<&Player>,
+) {
+    let number = |entity: Entity| players.get(entity).map_or(0, |player| player.player_number);
+    for throw in ev_throw.read() {
+        println!(
+            "Player {:?} grabbed player {:?}",
+            number(throw.thrower),
+            number(throw.defender)
+        );
+    }
+    for tech in ev_tech.read() {
+        println!(
+            "Player {:?} teched player {:?}'s throw",
+            number(tech.defender),
+            number(tech.thrower)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fighter::FIGHTERS, input::InputSample, player::PlayerStateChangeEvent};
+
+    fn fighter_index(name: &str) -> usize {
+        FIGHTERS
+            .iter()
+            .position(|fighter| fighter.name == name)
+            .unwrap()
+    }
+
+    fn throw_app() -> App {
+        let mut app = App::new();
+        app.add_event::<ThrowEvent>()
+            .add_event::<PlayerStateChangeEvent>()
+            .insert_resource(ThrowRules::default())
+            .insert_resource(ClashRules::default())
+            .insert_resource(ClashCounter::default())
+            .insert_resource(MatchSettings::default())
+            .add_systems(Update, attempt_throws);
+        app
+    }
+
+    /// A fighter pressing throw with the cooldown over.
+    fn spawn_thrower(app: &mut App, fighter: usize) -> Entity {
+        let mut ready = Timer::from_seconds(0.0, TimerMode::Once);
+        ready.tick(Duration::ZERO);
+        let mut input = PlayerInput::default();
+        input.push(InputSample {
+            throw: true,
+            ..default()
+        });
+        app.world
+            .spawn((
+                Player {
+                    fighter,
+                    team: 1,
+                    attack_timer: ready,
+                    ..default()
+                },
+                input,
+                Transform::default(),
+            ))
+            .id()
+    }
+
+    fn spawn_defender(app: &mut App, player: Player, x: f32) -> Entity {
+        app.world
+            .spawn((
+                Player { team: 2, ..player },
+                PlayerInput::default(),
+                Transform::from_xyz(x, 0.0, 0.0),
+            ))
+            .id()
+    }
+
+    #[test]
+    fn a_fighter_who_cannot_be_grabbed_is_left_alone() {
+        let mut app = throw_app();
+        let thrower = spawn_thrower(&mut app, 0);
+        let defender = spawn_defender(
+            &mut app,
+            Player {
+                state: PlayerState::Clashing,
+                ..default()
+            },
+            100.0,
+        );
+
+        app.update();
+        let thrower = app.world.get::<Player>(thrower).unwrap();
+        assert_eq!(thrower.state, PlayerState::Wiff);
+        assert!(!thrower.attack_timer.finished());
+        let defender = app.world.get::<Player>(defender).unwrap();
+        assert_eq!(defender.state, PlayerState::Clashing);
+        assert_eq!(defender.last_hit_by, None);
+        assert_eq!(
+            defender.parry_timer.duration(),
+            Player::default().parry_timer.duration()
+        );
+    }
+
+    #[test]
+    fn touching_fighters_of_any_size_can_be_thrown() {
+        for name in ["Dart", "Square", "Slab"] {
+            let mut app = throw_app();
+            let fighter = fighter_index(name);
+            let thrower = spawn_thrower(&mut app, fighter);
+            let defender = spawn_defender(
+                &mut app,
+                Player {
+                    fighter,
+                    ..default()
+                },
+                FIGHTERS[fighter].size,
+            );
+
+            app.update();
+            let state = |entity| app.world.get::<Player>(entity).unwrap().state;
+            assert_eq!(state(thrower), PlayerState::Throwing, "{}", name);
+            assert_eq!(state(defender), PlayerState::Thrown, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,872 @@
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    player::{Lineup, LineupEntry},
+    profile::{not_editing, PlayerProfile},
+    round::RoundEnded,
+    save::SaveData,
+    ControlDevice, GameState, RoundOver,
+};
+
+/// Office tournaments: profiles are seeded by rating into a single or double
+/// elimination bracket, and each bracket match is a best-of-N on the shared keyboard.
+/// In double elimination, a grand final lost by the winners-bracket finalist is
+/// played again, since that is their first loss.
+pub struct TournamentPlugin;
+
+impl Plugin for TournamentPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Tournament::default())
+            .add_systems(OnEnter(GameState::Bracket), spawn_bracket_screen)
+            .add_systems(OnExit(GameState::Bracket), despawn_bracket_screen)
+            .add_systems(
+                Update,
+                (
+                    open_tournament
+                        .run_if(in_state(GameState::Lobby))
+                        .run_if(not_editing),
+                    (tournament_input, update_bracket_screen)
+                        .chain()
+                        .run_if(in_state(GameState::Bracket)),
+                    (count_bracket_rounds, leave_decided_match).run_if(in_state(GameState::Match)),
+                ),
+            );
+    }
+}
+
+pub const STARTING_RATING: f32 = 1500.0;
+const K_FACTOR: f32 = 32.0;
+const MIN_ENTRANTS: usize = 4;
+const MAX_ENTRANTS: usize = 16;
+const BEST_OF_CHOICES: [u32; 3] = [1, 3, 5];
+
+/// One finished best-of-N between two profiles; ratings are replayed from these.
+#[derive(Debug, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+pub struct MatchResult {
+    pub winner: String,
+    pub loser: String,
+}
+
+/// Elo ratings from the whole match history, oldest first. Profiles without
+/// matches are at [`STARTING_RATING`].
+pub fn ratings(history: &[MatchResult]) -> HashMap<String, f32> {
+    let mut ratings: HashMap<String, f32> = HashMap::default();
+    for result in history {
+        let winner = *ratings.get(&result.winner).unwrap_or(&STARTING_RATING);
+        let loser = *ratings.get(&result.loser).unwrap_or(&STARTING_RATING);
+        let expected = 1.0 / (1.0 + 10f32.powf((loser - winner) / 400.0));
+        let change = K_FACTOR * (1.0 - expected);
+        ratings.insert(result.winner.clone(), winner + change);
+        ratings.insert(result.loser.clone(), loser - change);
+    }
+    ratings
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Elimination {
+    #[default]
+    Single,
+    Double,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Entrant {
+    Profile(String),
+    /// An empty seed; whoever meets it goes through without playing.
+    Bye,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Winners,
+    Losers,
+    /// Round 0 is the grand final, round 1 the reset.
+    GrandFinal,
+}
+
+/// Where the winner or loser of a match goes next.
+#[derive(Debug, Clone, Copy)]
+struct Feed {
+    match_index: usize,
+    slot: usize,
+}
+
+#[derive(Debug, Clone)]
+struct BracketMatch {
+    section: Section,
+    round: usize,
+    entrants: [Option<Entrant>; 2],
+    wins: [u32; 2],
+    /// Which slot won.
+    winner: Option<usize>,
+    winner_to: Option<Feed>,
+    loser_to: Option<Feed>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Bracket {
+    matches: Vec<BracketMatch>,
+    final_match: usize,
+    best_of: u32,
+}
+
+/// Seed numbers in bracket order, so the top seeds only meet in the last rounds.
+fn seed_order(size: usize) -> Vec<usize> {
+    let mut order = vec![1];
+    while order.len() < size {
+        let len = order.len() * 2;
+        order = order
+            .into_iter()
+            .flat_map(|seed| [seed, len + 1 - seed])
+            .collect();
+    }
+    order
+}
+
+impl Bracket {
+    /// `seeded` is best first. Missing seeds up to the next power of two are byes.
+    pub fn new(seeded: &[String], elimination: Elimination, best_of: u32) -> Bracket {
+        let size = seeded.len().next_power_of_two().max(2);
+        let rounds = size.trailing_zeros() as usize;
+        let mut matches = Vec::new();
+        let add_round = |matches: &mut Vec<BracketMatch>, section, round, count| {
+            (0..count)
+                .map(|_| {
+                    matches.push(BracketMatch {
+                        section,
+                        round,
+                        entrants: [None, None],
+                        wins: [0, 0],
+                        winner: None,
+                        winner_to: None,
+                        loser_to: None,
+                    });
+                    matches.len() - 1
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let winners: Vec<Vec<usize>> = (0..rounds)
+            .map(|round| add_round(&mut matches, Section::Winners, round, size >> (round + 1)))
+            .collect();
+        let order = seed_order(size);
+        for (i, &index) in winners[0].iter().enumerate() {
+            for slot in 0..2 {
+                let entrant = seeded
+                    .get(order[2 * i + slot] - 1)
+                    .map_or(Entrant::Bye, |name| Entrant::Profile(name.clone()));
+                matches[index].entrants[slot] = Some(entrant);
+            }
+        }
+        for round in 1..rounds {
+            for (i, &index) in winners[round].iter().enumerate() {
+                for slot in 0..2 {
+                    matches[winners[round - 1][2 * i + slot]].winner_to = Some(Feed {
+                        match_index: index,
+                        slot,
+                    });
+                }
+            }
+        }
+        let mut final_match = winners[rounds - 1][0];
+
+        if elimination == Elimination::Double {
+            // Losers rounds alternate: survivors play each other, then the next
+            // batch of winners-bracket losers drops in against them.
+            let losers: Vec<Vec<usize>> = (0..2 * (rounds - 1))
+                .map(|round| {
+                    add_round(
+                        &mut matches,
+                        Section::Losers,
+                        round,
+                        size >> (round / 2 + 2),
+                    )
+                })
+                .collect();
+            for (i, &index) in winners[0].iter().enumerate() {
+                matches[index].loser_to = Some(Feed {
+                    match_index: losers[0][i / 2],
+                    slot: i % 2,
+                });
+            }
+            for round in 1..rounds {
+                for (i, &index) in winners[round].iter().enumerate() {
+                    matches[index].loser_to = Some(Feed {
+                        match_index: losers[2 * round - 1][i],
+                        slot: 1,
+                    });
+                }
+            }
+            for round in 0..losers.len() - 1 {
+                for (i, &index) in losers[round].iter().enumerate() {
+                    matches[index].winner_to = Some(if round % 2 == 0 {
+                        Feed {
+                            match_index: losers[round + 1][i],
+                            slot: 0,
+                        }
+                    } else {
+                        Feed {
+                            match_index: losers[round + 1][i / 2],
+                            slot: i % 2,
+                        }
+                    });
+                }
+            }
+
+            let grand_final = add_round(&mut matches, Section::GrandFinal, 0, 1)[0];
+            let reset = add_round(&mut matches, Section::GrandFinal, 1, 1)[0];
+            matches[final_match].winner_to = Some(Feed {
+                match_index: grand_final,
+                slot: 0,
+            });
+            matches[losers[losers.len() - 1][0]].winner_to = Some(Feed {
+                match_index: grand_final,
+                slot: 1,
+            });
+            matches[grand_final].winner_to = Some(Feed {
+                match_index: reset,
+                slot: 0,
+            });
+            matches[grand_final].loser_to = Some(Feed {
+                match_index: reset,
+                slot: 1,
+            });
+            final_match = reset;
+        }
+
+        let mut bracket = Bracket {
+            matches,
+            final_match,
+            best_of,
+        };
+        bracket.advance_byes();
+        bracket
+    }
+
+    pub fn wins_needed(&self) -> u32 {
+        self.best_of / 2 + 1
+    }
+
+    fn finish(&mut self, index: usize, winner: usize) {
+        let bracket_match = &mut self.matches[index];
+        bracket_match.winner = Some(winner);
+        let winner_entrant = bracket_match.entrants[winner].clone();
+        let mut loser_entrant = bracket_match.entrants[1 - winner].clone();
+        // The winners-bracket finalist winning the grand final leaves the reset with a
+        // bye, so it is settled without being played.
+        if bracket_match.section == Section::GrandFinal && bracket_match.round == 0 && winner == 0 {
+            loser_entrant = Some(Entrant::Bye);
+        }
+        if let Some(feed) = bracket_match.winner_to {
+            self.matches[feed.match_index].entrants[feed.slot] = winner_entrant;
+        }
+        if let Some(feed) = self.matches[index].loser_to {
+            self.matches[feed.match_index].entrants[feed.slot] = loser_entrant;
+        }
+    }
+
+    /// Settles every match that has a bye in it, until only real matches are left.
+    fn advance_byes(&mut self) {
+        while let Some(index) = self.matches.iter().position(|bracket_match| {
+            bracket_match.winner.is_none()
+                && bracket_match.entrants.iter().all(Option::is_some)
+                && bracket_match.entrants.contains(&Some(Entrant::Bye))
+        }) {
+            let winner = if self.matches[index].entrants[0] == Some(Entrant::Bye) {
+                1
+            } else {
+                0
+            };
+            self.finish(index, winner);
+        }
+    }
+
+    /// The next match that is ready to play.
+    fn next_match(&self) -> Option<usize> {
+        self.matches.iter().position(|bracket_match| {
+            bracket_match.winner.is_none() && bracket_match.entrants.iter().all(Option::is_some)
+        })
+    }
+
+    fn name(&self, index: usize, slot: usize) -> Option<&str> {
+        match &self.matches[index].entrants[slot] {
+            Some(Entrant::Profile(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn champion(&self) -> Option<&str> {
+        let winner = self.matches[self.final_match].winner?;
+        self.name(self.final_match, winner)
+    }
+}
+
+/// Setup choices, and the bracket once it has started.
+#[derive(Debug, Resource)]
+pub struct Tournament {
+    cursor: usize,
+    entered: Vec<String>,
+    elimination: Elimination,
+    best_of: u32,
+    bracket: Option<Bracket>,
+    /// The bracket match being played right now.
+    current: Option<usize>,
+    /// The match just finished; the bracket comes back once the winner is announced.
+    decided: bool,
+}
+
+impl Default for Tournament {
+    fn default() -> Self {
+        Tournament {
+            cursor: 0,
+            entered: Vec::new(),
+            elimination: Elimination::default(),
+            best_of: 3,
+            bracket: None,
+            current: None,
+            decided: false,
+        }
+    }
+}
+
+impl Tournament {
+    pub fn is_running(&self) -> bool {
+        self.bracket.is_some()
+    }
+}
+
+#[derive(Component)]
+struct BracketScreen;
+
+#[derive(Component)]
+struct BracketText;
+
+fn open_tournament(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F10) {
+        next_state.set(GameState::Bracket);
+    }
+}
+
+/// Profiles best first, with their ratings.
+fn leaderboard(save: &SaveData) -> Vec<(String, f32)> {
+    let ratings = ratings(&save.match_history);
+    let mut board: Vec<_> = save
+        .profiles
+        .iter()
+        .map(|profile| {
+            let rating = *ratings.get(&profile.name).unwrap_or(&STARTING_RATING);
+            (profile.name.clone(), rating)
+        })
+        .collect();
+    board.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    board
+}
+
+fn tournament_input(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    save: Res<SaveData>,
+    mut tournament: ResMut<Tournament>,
+    mut lineup: ResMut<Lineup>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        *tournament = Tournament::default();
+        next_state.set(GameState::Lobby);
+        return;
+    }
+
+    let Some(bracket) = &tournament.bracket else {
+        let board = leaderboard(&save);
+        if keyboard_input.just_pressed(KeyCode::ArrowDown) && tournament.cursor + 1 < board.len() {
+            tournament.cursor += 1;
+        }
+        if keyboard_input.just_pressed(KeyCode::ArrowUp) {
+            tournament.cursor = tournament.cursor.saturating_sub(1);
+        }
+        if keyboard_input.just_pressed(KeyCode::Space) {
+            if let Some((name, _)) = board.get(tournament.cursor) {
+                if let Some(position) = tournament
+                    .entered
+                    .iter()
+                    .position(|entered| entered == name)
+                {
+                    tournament.entered.remove(position);
+                } else if tournament.entered.len() < MAX_ENTRANTS {
+                    tournament.entered.push(name.clone());
+                }
+            }
+        }
+        if keyboard_input.just_pressed(KeyCode::KeyE) {
+            tournament.elimination = match tournament.elimination {
+                Elimination::Single => Elimination::Double,
+                Elimination::Double => Elimination::Single,
+            };
+        }
+        if keyboard_input.just_pressed(KeyCode::KeyB) {
+            let index = BEST_OF_CHOICES
+                .iter()
+                .position(|best_of| *best_of == tournament.best_of)
+                .unwrap_or(0);
+            tournament.best_of = BEST_OF_CHOICES[(index + 1) % BEST_OF_CHOICES.len()];
+        }
+        if keyboard_input.just_pressed(KeyCode::Enter) && tournament.entered.len() >= MIN_ENTRANTS {
+            let seeded: Vec<String> = board
+                .into_iter()
+                .map(|(name, _)| name)
+                .filter(|name| tournament.entered.contains(name))
+                .collect();
+            tournament.bracket = Some(Bracket::new(
+                &seeded,
+                tournament.elimination,
+                tournament.best_of,
+            ));
+        }
+        return;
+    };
+
+    if !keyboard_input.just_pressed(KeyCode::Enter) {
+        return;
+    }
+    if bracket.champion().is_some() {
+        *tournament = Tournament::default();
+        next_state.set(GameState::Lobby);
+        return;
+    }
+    let Some(index) = bracket.next_match() else {
+        return;
+    };
+
+    lineup.0 = bracket_lineup(bracket, index, &save);
+    let bracket = tournament.bracket.as_mut().unwrap();
+    bracket.matches[index].wins = [0, 0];
+    tournament.current = Some(index);
+    tournament.decided = false;
+    next_state.set(GameState::Match);
+}
+
+/// Both fighters share the keyboard: first layout on the left, second on the right.
+/// Each plays the fighter their profile last picked in the lobby.
+fn bracket_lineup(bracket: &Bracket, index: usize, save: &SaveData) -> Vec<LineupEntry> {
+    (0..2)
+        .map(|slot| {
+            let profile = bracket.name(index, slot);
+            let fighter = save
+                .profiles
+                .iter()
+                .find(|saved| Some(saved.name.as_str()) == profile)
+                .map_or(0, |saved| saved.fighter);
+            LineupEntry {
+                player_number: slot as u8 + 1,
+                controls: Some(ControlDevice::Keyboard(save.keyboard_layouts[slot])),
+                fighter,
+                color: save.video.palettes.colors()[slot],
+                palette: Some(slot),
+                profile: profile.map(str::to_string),
+            }
+        })
+        .collect()
+}
+
+fn count_bracket_rounds(
+    mut ev_round_over: EventReader<RoundOver>,
+    profiles: Query<&PlayerProfile>,
+    mut tournament: ResMut<Tournament>,
+    mut save: ResMut<SaveData>,
+) {
+    for round in ev_round_over.read() {
+        let Some(index) = tournament.current else {
+            continue;
+        };
+        let Some(bracket) = tournament.bracket.as_mut() else {
+            continue;
+        };
+        let winners: Vec<&str> = profiles
+            .iter_many(round.winners.iter())
+            .map(|profile| profile.0.as_str())
+            .collect();
+        let Some(slot) = (0..2).find(|slot| {
+            bracket
+                .name(index, *slot)
+                .is_some_and(|name| winners.contains(&name))
+        }) else {
+            continue;
+        };
+
+        bracket.matches[index].wins[slot] += 1;
+        if bracket.matches[index].wins[slot] < bracket.wins_needed() {
+            continue;
+        }
+
+        let winner = bracket.name(index, slot).unwrap_or_default().to_string();
+        let loser = bracket
+            .name(index, 1 - slot)
+            .unwrap_or_default()
+            .to_string();
+        println!("{} beats {}", winner, loser);
+        save.match_history.push(MatchResult { winner, loser });
+        bracket.finish(index, slot);
+        bracket.advance_byes();
+        tournament.current = None;
+        tournament.decided = true;
+    }
+}
+
+fn leave_decided_match(
+    mut ev_round_ended: EventReader<RoundEnded>,
+    mut tournament: ResMut<Tournament>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if ev_round_ended.read().count() > 0 && tournament.decided {
+        tournament.decided = false;
+        next_state.set(GameState::Bracket);
+    }
+}
+
+fn spawn_bracket_screen(mut commands: Commands) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: Color::rgb(0.05, 0.05, 0.08).into(),
+            z_index: ZIndex::Global(10),
+            ..default()
+        })
+        .insert(BracketScreen)
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle {
+                    text: Text {
+                        sections: vec![TextSection {
+                            value: String::new(),
+                            style: TextStyle {
+                                font_size: 28.0,
+                                color: Color::WHITE,
+                                font: Default::default(),
+                            },
+                        }],
+                        justify: JustifyText::Left,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(BracketText);
+        });
+}
+
+fn despawn_bracket_screen(mut commands: Commands, query: Query<Entity, With<BracketScreen>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn setup_text(tournament: &Tournament, save: &SaveData) -> String {
+    let mut text = format!(
+        "TOURNAMENT  {:?} elimination, best of {}\n\
+         Up/Down pick, Space enter/leave, E elimination, B best of, Enter start ({}-{} profiles), Esc back\n\n\
+         Leaderboard\n",
+        tournament.elimination, tournament.best_of, MIN_ENTRANTS, MAX_ENTRANTS
+    );
+    for (i, (name, rating)) in leaderboard(save).iter().enumerate() {
+        let (wins, losses) = save.match_history.iter().fold((0, 0), |(w, l), result| {
+            (
+                w + (result.winner == *name) as u32,
+                l + (result.loser == *name) as u32,
+            )
+        });
+        text.push_str(&format!(
+            "{} {:>2}. [{}] {:<12} {:>5.0}  {}-{}\n",
+            if i == tournament.cursor { ">" } else { " " },
+            i + 1,
+            if tournament.entered.contains(name) {
+                "x"
+            } else {
+                " "
+            },
+            name,
+            rating,
+            wins,
+            losses,
+        ));
+    }
+    if save.profiles.is_empty() {
+        text.push_str("  No profiles yet; make some in the lobby with F8\n");
+    }
+    text
+}
+
+fn bracket_text(bracket: &Bracket, current: Option<usize>) -> String {
+    let mut text = format!("TOURNAMENT  best of {}\n", bracket.best_of);
+    let mut heading = None;
+    for (index, bracket_match) in bracket.matches.iter().enumerate() {
+        // Matches with a bye were never played.
+        if bracket_match.entrants.contains(&Some(Entrant::Bye)) {
+            continue;
+        }
+        let this_heading = (bracket_match.section, bracket_match.round);
+        if heading != Some(this_heading) {
+            heading = Some(this_heading);
+            text.push_str(&match bracket_match.section {
+                Section::Winners => format!("\nRound {}\n", bracket_match.round + 1),
+                Section::Losers => format!("\nLosers round {}\n", bracket_match.round + 1),
+                Section::GrandFinal if bracket_match.round == 0 => "\nGrand final\n".to_string(),
+                Section::GrandFinal => "\nGrand final reset\n".to_string(),
+            });
+        }
+        let name = |slot: usize| bracket.name(index, slot).unwrap_or("?");
+        let marker = |slot: usize| {
+            if bracket_match.winner == Some(slot) {
+                "*"
+            } else {
+                " "
+            }
+        };
+        text.push_str(&format!(
+            "  {}{} {} - {} {}{}{}\n",
+            marker(0),
+            name(0),
+            bracket_match.wins[0],
+            bracket_match.wins[1],
+            name(1),
+            marker(1),
+            if current == Some(index) || bracket.next_match() == Some(index) {
+                "   <- next"
+            } else {
+                ""
+            },
+        ));
+    }
+
+    match bracket.champion() {
+        Some(champion) => text.push_str(&format!("\n{} wins the tournament! Enter to finish\n", champion)),
+        None => text.push_str(&format!(
+            "\nEnter to play the next match ({} on the first keys, the other on the second), Esc to abandon\n",
+            if let Some(index) = bracket.next_match() {
+                bracket.name(index, 0).unwrap_or("?")
+            } else {
+                "?"
+            }
+        )),
+    }
+    text
+}
+
+fn update_bracket_screen(
+    tournament: Res<Tournament>,
+    save: Res<SaveData>,
+    mut text: Query<&mut Text, With<BracketText>>,
+) {
+    let Ok(mut text) = text.get_single_mut() else {
+        return;
+    };
+    let value = match &tournament.bracket {
+        Some(bracket) => bracket_text(bracket, tournament.current),
+        None => setup_text(&tournament, &save),
+    };
+    if text.sections[0].value != value {
+        text.sections[0].value = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::Profile;
+
+    fn names(count: usize) -> Vec<String> {
+        ["A", "B", "C", "D", "E", "F", "G", "H"][..count]
+            .iter()
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    fn entrants(bracket: &Bracket, index: usize) -> [Option<&str>; 2] {
+        [bracket.name(index, 0), bracket.name(index, 1)]
+    }
+
+    fn first_round(bracket: &Bracket) -> Vec<[Option<&str>; 2]> {
+        (0..bracket.matches.len())
+            .filter(|index| {
+                let bracket_match = &bracket.matches[*index];
+                bracket_match.section == Section::Winners && bracket_match.round == 0
+            })
+            .map(|index| entrants(bracket, index))
+            .collect()
+    }
+
+    fn find(bracket: &Bracket, section: Section, round: usize) -> usize {
+        bracket
+            .matches
+            .iter()
+            .position(|bracket_match| {
+                bracket_match.section == section && bracket_match.round == round
+            })
+            .unwrap()
+    }
+
+    /// Plays the next match, won by `winner`.
+    fn play(bracket: &mut Bracket, winner: &str) {
+        let index = bracket.next_match().expect("a match to play");
+        let slot = (0..2)
+            .find(|slot| bracket.name(index, *slot) == Some(winner))
+            .unwrap_or_else(|| panic!("{} isn't in the next match", winner));
+        bracket.finish(index, slot);
+        bracket.advance_byes();
+    }
+
+    #[test]
+    fn seed_order_keeps_top_seeds_apart() {
+        assert_eq!(seed_order(2), vec![1, 2]);
+        assert_eq!(seed_order(4), vec![1, 4, 2, 3]);
+        assert_eq!(seed_order(8), vec![1, 8, 4, 5, 2, 7, 3, 6]);
+    }
+
+    #[test]
+    fn three_entrants_give_the_top_seed_a_bye() {
+        let bracket = Bracket::new(&names(3), Elimination::Single, 1);
+        assert_eq!(
+            first_round(&bracket),
+            vec![[Some("A"), None], [Some("B"), Some("C")]]
+        );
+        let final_match = find(&bracket, Section::Winners, 1);
+        assert_eq!(entrants(&bracket, final_match), [Some("A"), None]);
+        assert_eq!(bracket.next_match(), Some(1));
+    }
+
+    #[test]
+    fn five_entrants_give_the_top_three_seeds_byes() {
+        let bracket = Bracket::new(&names(5), Elimination::Single, 1);
+        assert_eq!(
+            first_round(&bracket),
+            vec![
+                [Some("A"), None],
+                [Some("D"), Some("E")],
+                [Some("B"), None],
+                [Some("C"), None],
+            ]
+        );
+        let semifinals: Vec<_> = (0..2)
+            .map(|i| entrants(&bracket, find(&bracket, Section::Winners, 1) + i))
+            .collect();
+        assert_eq!(semifinals, vec![[Some("A"), None], [Some("B"), Some("C")]]);
+        assert_eq!(bracket.next_match(), Some(1));
+    }
+
+    #[test]
+    fn eight_entrants_have_no_byes() {
+        let bracket = Bracket::new(&names(8), Elimination::Single, 3);
+        assert_eq!(
+            first_round(&bracket),
+            vec![
+                [Some("A"), Some("H")],
+                [Some("D"), Some("E")],
+                [Some("B"), Some("G")],
+                [Some("C"), Some("F")],
+            ]
+        );
+        assert!(bracket
+            .matches
+            .iter()
+            .all(|bracket_match| bracket_match.winner.is_none()));
+        assert_eq!(bracket.wins_needed(), 2);
+    }
+
+    #[test]
+    fn losers_drop_into_the_losers_bracket() {
+        let mut bracket = Bracket::new(&names(4), Elimination::Double, 1);
+        play(&mut bracket, "A");
+        play(&mut bracket, "B");
+        let losers_first = find(&bracket, Section::Losers, 0);
+        assert_eq!(entrants(&bracket, losers_first), [Some("D"), Some("C")]);
+
+        play(&mut bracket, "A");
+        let losers_final = find(&bracket, Section::Losers, 1);
+        assert_eq!(entrants(&bracket, losers_final), [None, Some("B")]);
+
+        play(&mut bracket, "C");
+        assert_eq!(entrants(&bracket, losers_final), [Some("C"), Some("B")]);
+        play(&mut bracket, "B");
+        let grand_final = find(&bracket, Section::GrandFinal, 0);
+        assert_eq!(entrants(&bracket, grand_final), [Some("A"), Some("B")]);
+    }
+
+    /// Plays a four-entrant double elimination bracket up to the grand final, A against B.
+    fn up_to_grand_final() -> Bracket {
+        let mut bracket = Bracket::new(&names(4), Elimination::Double, 1);
+        for winner in ["A", "B", "A", "C", "B"] {
+            play(&mut bracket, winner);
+        }
+        bracket
+    }
+
+    #[test]
+    fn winners_finalist_taking_the_grand_final_skips_the_reset() {
+        let mut bracket = up_to_grand_final();
+        play(&mut bracket, "A");
+        assert_eq!(bracket.champion(), Some("A"));
+        assert_eq!(bracket.next_match(), None);
+    }
+
+    #[test]
+    fn losers_finalist_taking_the_grand_final_forces_a_reset() {
+        let mut bracket = up_to_grand_final();
+        play(&mut bracket, "B");
+        assert_eq!(bracket.champion(), None);
+        let reset = find(&bracket, Section::GrandFinal, 1);
+        assert_eq!(bracket.next_match(), Some(reset));
+        assert_eq!(entrants(&bracket, reset), [Some("B"), Some("A")]);
+
+        play(&mut bracket, "A");
+        assert_eq!(bracket.champion(), Some("A"));
+    }
+
+    fn result(winner: &str, loser: &str) -> MatchResult {
+        MatchResult {
+            winner: winner.to_string(),
+            loser: loser.to_string(),
+        }
+    }
+
+    #[test]
+    fn ratings_replay_the_history_in_order() {
+        assert!(ratings(&[]).is_empty());
+
+        let first = ratings(&[result("A", "B")]);
+        assert_eq!(first["A"], STARTING_RATING + K_FACTOR / 2.0);
+        assert_eq!(first["B"], STARTING_RATING - K_FACTOR / 2.0);
+
+        // B was rated lower going into the rematch, so winning it is worth more.
+        let rematch = ratings(&[result("A", "B"), result("B", "A")]);
+        assert!(rematch["B"] > rematch["A"]);
+        assert!((rematch["A"] + rematch["B"] - 2.0 * STARTING_RATING).abs() < 1e-3);
+        let swapped = ratings(&[result("B", "A"), result("A", "B")]);
+        assert!(swapped["A"] > swapped["B"]);
+    }
+
+    #[test]
+    fn bracket_matches_use_each_profiles_fighter() {
+        let bracket = Bracket::new(&names(4), Elimination::Single, 1);
+        let index = bracket.next_match().unwrap();
+        assert_eq!(entrants(&bracket, index), [Some("A"), Some("D")]);
+
+        let mut save = SaveData::default();
+        save.profiles.push(Profile {
+            name: "A".to_string(),
+            fighter: 2,
+            ..default()
+        });
+        let lineup = bracket_lineup(&bracket, index, &save);
+        assert_eq!(lineup[0].fighter, 2);
+        // D's profile is gone from the save, so they get the first fighter.
+        assert_eq!(lineup[1].fighter, 0);
+    }
+}
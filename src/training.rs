@@ -0,0 +1,350 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{
+    input::{InputSample, InputSet, PlayerInput},
+    pause::not_paused,
+    player::{
+        secs_to_frames, Player, PlayerSlots, PlayerState, PlayerStateChangeEvent, ResetPlayers,
+        StartingDistance,
+    },
+    GameState,
+};
+
+pub struct TrainingPlugin;
+
+impl Plugin for TrainingPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<TrainingMode>()
+            .insert_resource(TrainingMode::default())
+            .insert_resource(ParryTimings::default())
+            .add_systems(Startup, spawn_training_overlay)
+            .add_systems(
+                Update,
+                (
+                    training_hotkeys
+                        .run_if(in_state(GameState::Match))
+                        .run_if(not_paused),
+                    drive_dummy.in_set(InputSet::Override),
+                    track_parry_timing.after(InputSet::Override),
+                    update_training_overlay,
+                ),
+            );
+    }
+}
+
+/// How far either side of the parry window a press is still reported as early or late.
+const PARRY_REPORT_WINDOW_FRAMES: u32 = 20;
+
+/// Spacings the training reset hotkeys (1, 2, 3) put the fighters at.
+const SPACING_PRESETS: [f32; 3] = [150.0, 600.0, 1200.0];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Reflect)]
+pub enum DummyBehavior {
+    #[default]
+    Stand,
+    Walk,
+    AttackLoop,
+    Replay,
+}
+
+impl DummyBehavior {
+    fn next(self) -> Self {
+        match self {
+            DummyBehavior::Stand => DummyBehavior::Walk,
+            DummyBehavior::Walk => DummyBehavior::AttackLoop,
+            DummyBehavior::AttackLoop => DummyBehavior::Replay,
+            DummyBehavior::Replay => DummyBehavior::Stand,
+        }
+    }
+}
+
+#[derive(Debug, Resource, Reflect)]
+#[reflect(Resource)]
+pub struct TrainingMode {
+    pub enabled: bool,
+    pub dummy_player: u8,
+    pub dummy: DummyBehavior,
+    pub attack_interval_frames: u32,
+    /// While recording, the dummy is driven by its own keys and every frame is stored here.
+    pub recording: Vec<InputSample>,
+    pub is_recording: bool,
+    replay_frame: usize,
+    loop_frame: u32,
+}
+
+impl Default for TrainingMode {
+    fn default() -> Self {
+        TrainingMode {
+            enabled: false,
+            dummy_player: 2,
+            dummy: DummyBehavior::default(),
+            attack_interval_frames: 90,
+            recording: Vec::new(),
+            is_recording: false,
+            replay_frame: 0,
+            loop_frame: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParryTiming {
+    Early(u32),
+    OnTime(u32),
+    Late(u32),
+}
+
+#[derive(Debug, Default)]
+struct ParryTracker {
+    last_press: Option<f32>,
+    hit_at: Option<f32>,
+    dead_at: Option<f32>,
+    result: Option<ParryTiming>,
+}
+
+#[derive(Debug, Default, Resource)]
+struct ParryTimings(HashMap<Entity, ParryTracker>);
+
+#[derive(Component)]
+struct TrainingOverlay;
+
+fn training_hotkeys(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut training: ResMut<TrainingMode>,
+    mut starting_distance: ResMut<StartingDistance>,
+    mut ev_reset: EventWriter<ResetPlayers>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F1) {
+        training.enabled = !training.enabled;
+        training.is_recording = false;
+        if !training.enabled {
+            *starting_distance = StartingDistance::default();
+        }
+        ev_reset.send(ResetPlayers);
+    }
+    if !training.enabled {
+        return;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::F2) {
+        training.dummy = training.dummy.next();
+        training.replay_frame = 0;
+        training.loop_frame = 0;
+    }
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        if training.is_recording {
+            training.is_recording = false;
+            training.dummy = DummyBehavior::Replay;
+            training.replay_frame = 0;
+        } else {
+            training.is_recording = true;
+            training.recording.clear();
+        }
+    }
+
+    let presets = [KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3];
+    for (key, spacing) in presets.iter().zip(SPACING_PRESETS) {
+        if keyboard_input.just_pressed(*key) {
+            starting_distance.0 = spacing;
+            training.replay_frame = 0;
+            training.loop_frame = 0;
+            ev_reset.send(ResetPlayers);
+        }
+    }
+}
+
+fn drive_dummy(
+    mut training: ResMut<TrainingMode>,
+    mut query: Query<(&Transform, &mut PlayerInput)>,
+    slots: Res<PlayerSlots>,
+) {
+    if !training.enabled {
+        return;
+    }
+
+    let Some(dummy) = slots.get(training.dummy_player) else {
+        return;
+    };
+    let opponent_x = slots
+        .iter()
+        .find(|(_, entity)| *entity != dummy)
+        .and_then(|(_, entity)| query.get(entity).ok())
+        .map(|(transform, _)| transform.translation.x);
+
+    let Ok((transform, mut input)) = query.get_mut(dummy) else {
+        return;
+    };
+
+    if training.is_recording {
+        let sample = input.current;
+        training.recording.push(sample);
+        return;
+    }
+
+    let sample = match training.dummy {
+        DummyBehavior::Stand => InputSample::default(),
+        DummyBehavior::Walk => match opponent_x {
+            Some(x) => InputSample {
+                left: x < transform.translation.x,
+                right: x > transform.translation.x,
+                ..default()
+            },
+            None => InputSample::default(),
+        },
+        DummyBehavior::AttackLoop => {
+            training.loop_frame =
+                (training.loop_frame + 1) % training.attack_interval_frames.max(2);
+            InputSample {
+                attack: training.loop_frame == 0,
+                ..default()
+            }
+        }
+        DummyBehavior::Replay => {
+            if training.recording.is_empty() {
+                InputSample::default()
+            } else {
+                let sample = training.recording[training.replay_frame % training.recording.len()];
+                training.replay_frame = (training.replay_frame + 1) % training.recording.len();
+                sample
+            }
+        }
+    };
+    input.set_current(sample);
+}
+
+fn track_parry_timing(
+    time: Res<Time>,
+    mut timings: ResMut<ParryTimings>,
+    mut ev_player_state_change: EventReader<PlayerStateChangeEvent>,
+    query: Query<(Entity, &Player, &PlayerInput)>,
+) {
+    let now = time.elapsed_seconds();
+    let frames_since = |then: f32| secs_to_frames(now - then);
+
+    for event in ev_player_state_change.read() {
+        let tracker = timings.0.entry(event.0).or_default();
+        match event.1 {
+            PlayerState::TakingDamage => {
+                tracker.hit_at = Some(now);
+                tracker.dead_at = None;
+                if let Some(press) = tracker.last_press {
+                    if frames_since(press) <= PARRY_REPORT_WINDOW_FRAMES {
+                        tracker.result = Some(ParryTiming::Early(frames_since(press)));
+                    }
+                }
+            }
+            PlayerState::Clashing => {
+                if let Some(hit_at) = tracker.hit_at.take() {
+                    tracker.result = Some(ParryTiming::OnTime(frames_since(hit_at)));
+                }
+            }
+            // Missing the parry puts the fighter in hitstun, or down if the hit finishes them.
+            PlayerState::Dead | PlayerState::Hitstun => {
+                tracker.hit_at = None;
+                tracker.dead_at = Some(now);
+            }
+            PlayerState::Alive => {
+                tracker.hit_at = None;
+                tracker.dead_at = None;
+            }
+            PlayerState::Wiff
+            | PlayerState::Reversal
+            | PlayerState::LongStrike
+            | PlayerState::Firing
+            | PlayerState::Throwing
+            | PlayerState::Thrown
+            | PlayerState::Teching
+            | PlayerState::Dashing
+            | PlayerState::Backdashing => {}
+        }
+    }
+
+    for (entity, player, input) in query.iter() {
+        if !input.attack_just_pressed() {
+            continue;
+        }
+        let tracker = timings.0.entry(entity).or_default();
+        tracker.last_press = Some(now);
+        if matches!(player.state, PlayerState::Dead | PlayerState::Hitstun) {
+            if let Some(dead_at) = tracker.dead_at.take() {
+                if frames_since(dead_at) <= PARRY_REPORT_WINDOW_FRAMES {
+                    tracker.result = Some(ParryTiming::Late(frames_since(dead_at)));
+                }
+            }
+        }
+    }
+}
+
+fn spawn_training_overlay(mut commands: Commands) {
+    commands
+        .spawn(TextBundle {
+            text: Text {
+                sections: vec![TextSection {
+                    value: String::new(),
+                    style: TextStyle {
+                        font_size: 24.0,
+                        color: Color::WHITE,
+                        font: Default::default(),
+                    },
+                }],
+                justify: JustifyText::Left,
+                ..Default::default()
+            },
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Percent(20.0),
+                left: Val::Percent(2.0),
+                ..default()
+            },
+            visibility: Visibility::Hidden,
+            ..Default::default()
+        })
+        .insert(TrainingOverlay);
+}
+
+fn update_training_overlay(
+    training: Res<TrainingMode>,
+    timings: Res<ParryTimings>,
+    players: Query<(Entity, &Player)>,
+    mut overlay: Query<(&mut Text, &mut Visibility), With<TrainingOverlay>>,
+) {
+    let Ok((mut text, mut visibility)) = overlay.get_single_mut() else {
+        return;
+    };
+    if !training.enabled {
+        *visibility = Visibility::Hidden;
+        return;
+    }
+    *visibility = Visibility::Visible;
+
+    let dummy = if training.is_recording {
+        format!("recording ({} frames)", training.recording.len())
+    } else {
+        format!("{:?}", training.dummy)
+    };
+    let mut value = format!(
+        "TRAINING  dummy: {}  [F2 behavior, F3 record, 1/2/3 spacing]\n",
+        dummy
+    );
+
+    let mut players: Vec<_> = players.iter().collect();
+    players.sort_by_key(|(_, player)| player.player_number);
+    for (entity, player) in players {
+        let last_parry = match timings.0.get(&entity).and_then(|tracker| tracker.result) {
+            Some(ParryTiming::Early(frames)) => format!("early by {}f", frames),
+            Some(ParryTiming::OnTime(frames)) => format!("on time ({}f in)", frames),
+            Some(ParryTiming::Late(frames)) => format!("late by {}f", frames),
+            None => "-".to_string(),
+        };
+        value.push_str(&format!(
+            "P{} {:?}  attack: {}f  parry: {}f  clash: {}f  last parry: {}\n",
+            player.player_number,
+            player.state,
+            secs_to_frames(player.attack_timer.remaining_secs()),
+            secs_to_frames(player.parry_timer.remaining_secs()),
+            secs_to_frames(player.clashing_timer.remaining_secs()),
+            last_parry,
+        ));
+    }
+    text.sections[0].value = value;
+}